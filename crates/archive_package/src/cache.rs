@@ -0,0 +1,103 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use github_package::luap_home;
+use lua_workspace_config::workspace_config::ArchiveDependency;
+use sha2::{Digest, Sha256};
+
+/// Archives are kept under `$LUAP_HOME/cache/archive`, named by their sha256,
+/// so the same release is only downloaded once whatever url it came from.
+pub fn cache_archive_path(sha256: &str) -> PathBuf {
+    luap_home()
+        .join("cache")
+        .join("archive")
+        .join(sha256.to_ascii_lowercase())
+}
+
+/// Opens the cached copy of an archive without downloading anything.
+pub fn open_cached_archive(archive: &ArchiveDependency) -> Result<PathBuf, String> {
+    let sha256 = expected_sha256(archive)?;
+    let path = cache_archive_path(sha256);
+    let content = std::fs::read(&path).map_err(|_| {
+        format!(
+            "{} is not in the luap cache, run `luap install`",
+            archive.url
+        )
+    })?;
+    verify_sha256(archive, sha256, &content)?;
+    Ok(path)
+}
+
+/// Downloads or copies an archive into the cache. Nothing is cached unless it
+/// matches the sha256 the dependency asks for.
+pub fn fetch_archive(archive: &ArchiveDependency) -> Result<PathBuf, String> {
+    if let Ok(path) = open_cached_archive(archive) {
+        return Ok(path);
+    }
+
+    let sha256 = expected_sha256(archive)?;
+    let content = read_url(&archive.url)?;
+    verify_sha256(archive, sha256, &content)?;
+
+    let path = cache_archive_path(sha256);
+    write_atomic(&path, &content).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn expected_sha256(archive: &ArchiveDependency) -> Result<&str, String> {
+    match archive.sha256.as_deref() {
+        Some(sha256) if sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(sha256)
+        }
+        Some(sha256) => Err(format!("`{}` is not a sha256 of {}", sha256, archive.url)),
+        None => Err(format!("archive {} has no sha256", archive.url)),
+    }
+}
+
+fn verify_sha256(
+    archive: &ArchiveDependency,
+    expected: &str,
+    content: &[u8],
+) -> Result<(), String> {
+    let actual = format!("{:x}", Sha256::digest(content));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!(
+            "sha256 mismatch for {}: expected {}, got {}",
+            archive.url, expected, actual
+        ));
+    }
+    Ok(())
+}
+
+/// Downloads an `http(s)://` url, or reads a local file, which can be given
+/// as a path or a `file://` url.
+pub fn read_url(url: &str) -> Result<Vec<u8>, String> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        download(url)
+    } else {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        std::fs::read(path).map_err(|e| e.to_string())
+    }
+    .map_err(|e| format!("failed to fetch {}: {}", url, e))
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url).call().map_err(|e| e.to_string())?;
+    let mut content = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut content)
+        .map_err(|e| e.to_string())?;
+    Ok(content)
+}
+
+/// Writes a cache file so that a download that is cut off is never found in
+/// the cache next time.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,5 @@
+pub mod cache;
+pub mod unpack;
+
+pub use cache::{fetch_archive, open_cached_archive, read_url};
+pub use unpack::{modified_files, read_file, read_files, unpack_archive};
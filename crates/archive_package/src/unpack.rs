@@ -0,0 +1,271 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+
+/// A regular file of an archive, `path` already has the prefix stripped.
+struct Entry {
+    path: String,
+    content: Vec<u8>,
+    mode: Option<u32>,
+}
+
+/// Writes the files of `archive` under `strip_prefix` to `to_path`, replacing
+/// whatever was there.
+pub fn unpack_archive(
+    archive: &Path,
+    strip_prefix: Option<&str>,
+    to_path: &Path,
+) -> io::Result<()> {
+    let entries = read_entries(archive, strip_prefix)?;
+    if to_path.exists() {
+        std::fs::remove_dir_all(to_path)?;
+    }
+    std::fs::create_dir_all(to_path)?;
+
+    for entry in entries {
+        let path = to_path.join(&entry.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &entry.content)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one file of `archive`, such as its package.toml.
+pub fn read_file(
+    archive: &Path,
+    strip_prefix: Option<&str>,
+    name: &str,
+) -> io::Result<Option<Vec<u8>>> {
+    Ok(read_entries(archive, strip_prefix)?
+        .into_iter()
+        .find(|entry| entry.path == name)
+        .map(|entry| entry.content))
+}
+
+/// Files of `archive` that are missing from `dir` or differ from it. Files
+/// added next to them are not reported.
+pub fn modified_files(
+    archive: &Path,
+    strip_prefix: Option<&str>,
+    dir: &Path,
+) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in read_entries(archive, strip_prefix)? {
+        match std::fs::read(dir.join(&entry.path)) {
+            Ok(content) if content == entry.content => {}
+            _ => files.push(entry.path),
+        }
+    }
+    Ok(files)
+}
+
+/// Every file of `archive` under `strip_prefix` with its content.
+pub fn read_files(
+    archive: &Path,
+    strip_prefix: Option<&str>,
+) -> io::Result<Vec<(String, Vec<u8>)>> {
+    Ok(read_entries(archive, strip_prefix)?
+        .into_iter()
+        .map(|entry| (entry.path, entry.content))
+        .collect())
+}
+
+fn read_entries(archive: &Path, strip_prefix: Option<&str>) -> io::Result<Vec<Entry>> {
+    let mut magic = [0u8; 4];
+    let len = File::open(archive)?.read(&mut magic)?;
+    let magic = &magic[..len];
+
+    let entries = if magic.starts_with(&[0x1f, 0x8b]) {
+        read_tar(GzDecoder::new(File::open(archive)?))?
+    } else if magic.starts_with(b"PK") {
+        read_zip(File::open(archive)?)?
+    } else {
+        read_tar(File::open(archive)?)?
+    };
+
+    let prefix = strip_prefix
+        .map(|prefix| prefix.trim_matches('/'))
+        .filter(|prefix| !prefix.is_empty());
+    let mut stripped = Vec::new();
+    for mut entry in entries {
+        if let Some(prefix) = prefix {
+            match entry
+                .path
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => entry.path = rest.to_string(),
+                None => continue,
+            }
+        }
+        if !entry.path.is_empty() {
+            stripped.push(entry);
+        }
+    }
+    Ok(stripped)
+}
+
+fn read_tar(reader: impl Read) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = checked_path(&entry.path()?)?;
+        let mode = entry.header().mode().ok();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        entries.push(Entry {
+            path,
+            content,
+            mode,
+        });
+    }
+    Ok(entries)
+}
+
+fn read_zip(file: File) -> io::Result<Vec<Entry>> {
+    let mut zip = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(io::Error::other)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = checked_path(Path::new(file.name()))?;
+        let mode = file.unix_mode();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        entries.push(Entry {
+            path,
+            content,
+            mode,
+        });
+    }
+    Ok(entries)
+}
+
+// an entry must never be written outside of the package directory
+fn checked_path(path: &Path) -> io::Result<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsafe path in archive: {}", path.display()),
+                ))
+            }
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn tempdir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("luap_archive_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_tar_gz(path: &Path, files: &[(&str, &str)]) {
+        let encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_unpack_tar_gz_with_strip_prefix() {
+        let dir = tempdir("tar");
+        let archive = dir.join("json-1.0.0.tar.gz");
+        write_tar_gz(
+            &archive,
+            &[
+                ("json-1.0.0/json.lua", "return {}\n"),
+                ("json-1.0.0/package.toml", "[package]\nname = \"json\"\n"),
+                ("other/README", "skipped\n"),
+            ],
+        );
+
+        let to_path = dir.join("lua_modules/json");
+        unpack_archive(&archive, Some("json-1.0.0"), &to_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(to_path.join("json.lua")).unwrap(),
+            "return {}\n"
+        );
+        assert!(!to_path.join("other").exists());
+        assert_eq!(
+            read_file(&archive, Some("json-1.0.0/"), "package.toml").unwrap(),
+            Some(b"[package]\nname = \"json\"\n".to_vec())
+        );
+
+        assert!(modified_files(&archive, Some("json-1.0.0"), &to_path)
+            .unwrap()
+            .is_empty());
+        std::fs::write(to_path.join("json.lua"), "return nil\n").unwrap();
+        std::fs::write(to_path.join("extra.lua"), "return nil\n").unwrap();
+        assert_eq!(
+            modified_files(&archive, Some("json-1.0.0"), &to_path).unwrap(),
+            vec!["json.lua".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_unpack_zip() {
+        let dir = tempdir("zip");
+        let archive = dir.join("say.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("src/say.lua", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"return {}\n").unwrap();
+        zip.finish().unwrap();
+
+        let to_path = dir.join("say");
+        unpack_archive(&archive, None, &to_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(to_path.join("src/say.lua")).unwrap(),
+            "return {}\n"
+        );
+    }
+
+    #[test]
+    fn test_unpack_rejects_unsafe_paths() {
+        assert!(checked_path(Path::new("../evil.lua")).is_err());
+        assert!(checked_path(Path::new("/etc/evil.lua")).is_err());
+        assert_eq!(
+            checked_path(Path::new("./lib/ok.lua")).unwrap(),
+            "lib/ok.lua"
+        );
+    }
+}
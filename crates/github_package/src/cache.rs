@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    Error, FetchOptions, Oid, Repository, ResetType,
+};
+use lua_workspace_config::workspace_config::GithubDependency;
+
+use crate::{luap_home, normalize_repo_url, remote_callbacks, resolve_repo_url, tag::RemoteTag};
+
+/// Every remote is mirrored into a bare repository under `$LUAP_HOME/cache/git`,
+/// so manifests can be read at any revision before anything is checked out.
+pub fn cache_repo_path(url: &str) -> PathBuf {
+    let dir_name: String = normalize_repo_url(url)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    luap_home().join("cache").join("git").join(dir_name)
+}
+
+/// Opens the cache mirror of `url` without contacting the remote.
+pub fn open_cache(url: &str) -> Result<Repository, Error> {
+    Repository::open_bare(cache_repo_path(url))
+}
+
+/// Creates or refreshes the cache mirror of `url`.
+pub fn fetch_to_cache(url: &str) -> Result<Repository, Error> {
+    let repo_url = resolve_repo_url(url);
+    let cache_path = cache_repo_path(url);
+    let repo = if cache_path.exists() {
+        Repository::open_bare(&cache_path)?
+    } else {
+        std::fs::create_dir_all(&cache_path).map_err(|e| Error::from_str(&e.to_string()))?;
+        let repo = Repository::init_bare(&cache_path)?;
+        repo.remote("origin", &repo_url)?;
+        repo
+    };
+
+    {
+        let mut remote = repo.find_remote("origin")?;
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(remote_callbacks());
+        remote.fetch(
+            &["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"],
+            Some(&mut fetch_options),
+            None,
+        )?;
+
+        if let Ok(default_branch) = remote.default_branch() {
+            if let Some(default_branch) = default_branch.as_str() {
+                repo.set_head(default_branch)?;
+            }
+        }
+    }
+
+    Ok(repo)
+}
+
+pub fn list_tags(repo: &Repository) -> Result<Vec<RemoteTag>, Error> {
+    let mut tags = Vec::new();
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let commit = repo
+            .revparse_single(&format!("refs/tags/{}", name))?
+            .peel_to_commit()?;
+        tags.push(RemoteTag {
+            name: name.to_string(),
+            commit: commit.id().to_string(),
+        });
+    }
+    Ok(tags)
+}
+
+/// Finds the commit selected by `hash`, `tag` or `branch`, falling back to the
+/// default branch of the remote.
+pub fn resolve_revision(repo: &Repository, github_config: &GithubDependency) -> Result<Oid, Error> {
+    let spec = if let Some(hash) = &github_config.hash {
+        hash.clone()
+    } else if let Some(tag) = &github_config.tag {
+        format!("refs/tags/{}", tag)
+    } else if let Some(branch) = &github_config.branch {
+        format!("refs/heads/{}", branch)
+    } else {
+        "HEAD".to_string()
+    };
+
+    Ok(repo.revparse_single(&spec)?.peel_to_commit()?.id())
+}
+
+pub fn read_file_at_commit(
+    repo: &Repository,
+    commit: Oid,
+    file_path: &str,
+) -> Result<Option<String>, Error> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    let entry = match tree.get_path(Path::new(file_path)) {
+        Ok(entry) => entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let blob = repo.find_blob(entry.id())?;
+    Ok(Some(String::from_utf8_lossy(blob.content()).to_string()))
+}
+
+pub fn checked_out_commit(repo_path: &Path) -> Option<String> {
+    let repo = Repository::open(repo_path).ok()?;
+    let head = repo.head().ok()?;
+    head.target().map(|commit| commit.to_string())
+}
+
+/// Checks out `commit` of `url` into `to_path` from the cache mirror. An existing
+/// checkout is reset in place; a new one gets its `origin` pointed back at `url`.
+pub fn checkout_from_cache(url: &str, commit: &str, to_path: &Path) -> Result<(), Error> {
+    let cache_path = cache_repo_path(url);
+    let cache_url = cache_path.to_str().unwrap();
+    let commit = Oid::from_str(commit)?;
+
+    let repo = if let Ok(repo) = Repository::open(to_path) {
+        if repo.head().ok().and_then(|head| head.target()) == Some(commit) {
+            return Ok(());
+        }
+        if repo.find_commit(commit).is_err() {
+            repo.remote_anonymous(cache_url)?.fetch(
+                &[
+                    "+refs/tags/*:refs/tags/*",
+                    "+refs/heads/*:refs/remotes/origin/*",
+                ],
+                None,
+                None,
+            )?;
+        }
+        repo
+    } else {
+        let repo = RepoBuilder::new().clone(cache_url, to_path)?;
+        repo.remote_set_url("origin", &resolve_repo_url(url))?;
+        repo
+    };
+
+    if repo.head().is_ok() {
+        let obj = repo.find_object(commit, None)?;
+        repo.reset(&obj, ResetType::Hard, None)?;
+    } else {
+        // the mirror had no default branch to check out
+        repo.set_head_detached(commit)?;
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+    }
+
+    for mut submodule in repo.submodules()? {
+        submodule.update(true, None)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use git2::{
+    ObjectType, Oid, Repository, Status, StatusOptions, TreeWalkMode, TreeWalkResult,
+};
+use sha2::{Digest, Sha256};
+
+/// Content checksum of a package tree, independent of where the files came
+/// from: every file is hashed with its path relative to the package root, in
+/// path order. Nested repositories (submodules) are left out.
+#[derive(Default)]
+struct TreeHasher {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl TreeHasher {
+    fn add(&mut self, path: String, content: Vec<u8>) {
+        self.files.push((path, content));
+    }
+
+    fn finish(mut self) -> String {
+        self.files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut hasher = Sha256::new();
+        for (path, content) in &self.files {
+            hasher.update(path.as_bytes());
+            hasher.update([0]);
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(content);
+        }
+        format!("sha256:{:x}", hasher.finalize())
+    }
+}
+
+pub fn git_tree_checksum(repo: &Repository, commit: Oid) -> Result<String, git2::Error> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    let mut hasher = TreeHasher::default();
+    let mut error = None;
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        match repo.find_blob(entry.id()) {
+            Ok(blob) => {
+                let path = format!("{}{}", root, entry.name().unwrap_or_default());
+                hasher.add(path, blob.content().to_vec());
+                TreeWalkResult::Ok
+            }
+            Err(e) => {
+                error = Some(e);
+                TreeWalkResult::Abort
+            }
+        }
+    })?;
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(hasher.finish()),
+    }
+}
+
+/// Tracked files of the checkout at `path` that differ from its HEAD commit.
+/// Untracked and ignored files are not part of the package and are left out,
+/// line ending conversion (`core.autocrlf`) is undone by git itself.
+pub fn modified_files(path: &Path) -> Result<Vec<String>, git2::Error> {
+    let repo = Repository::open(path)?;
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(false)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut options))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| entry.path().map(|path| path.to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_tree_checksum_and_modified_files() {
+        let dir = std::env::temp_dir().join("luap_checksum_test");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("init.lua"), "return require('src.json')\n").unwrap();
+        std::fs::write(dir.join("src/json.lua"), "return {}\n").unwrap();
+
+        let repo = Repository::init(&dir).unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("luap", "luap@example.com").unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let checksum = git_tree_checksum(&repo, commit).unwrap();
+        assert!(checksum.starts_with("sha256:"));
+        assert!(modified_files(&dir).unwrap().is_empty());
+
+        // files that are not part of the commit do not count
+        std::fs::write(dir.join("package.lock"), "[dependencies]\n").unwrap();
+        assert!(modified_files(&dir).unwrap().is_empty());
+
+        std::fs::write(dir.join("src/json.lua"), "return { modified = true }\n").unwrap();
+        assert_eq!(modified_files(&dir).unwrap(), vec!["src/json.lua".to_string()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,51 +1,33 @@
-use git2::{build::RepoBuilder, Cred, FetchOptions, RemoteCallbacks};
+use git2::{build::RepoBuilder, FetchOptions};
 use lua_workspace_config::workspace_config::GithubDependency;
 use std::path::Path;
 
-use crate::{find_id_rsa, resolve_github_url};
+use crate::{remote_callbacks, resolve_repo_url};
 
 pub fn clone_and_init_submodules(
     github_config: &GithubDependency,
     to_path: &Path,
 ) -> Result<(), git2::Error> {
     // Set up callbacks for authentication
-    let mut callbacks = RemoteCallbacks::new();
-    let ssh_id_rsa = find_id_rsa();
-
-    if let Some(ssh_id_rsa) = ssh_id_rsa {
-        callbacks.credentials(move |_url, username_from_url, _allowed_types| {
-            Cred::ssh_key(
-                username_from_url.unwrap(),
-                None,
-                Path::new(&ssh_id_rsa),
-                None,
-            )
-        });
-    } else {
-        callbacks.credentials(|_url, username_from_url, _allowed_types| {
-            Cred::ssh_key_from_agent(username_from_url.unwrap())
-        });
-    }
-
     let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    fetch_options.remote_callbacks(remote_callbacks());
 
     let mut builder = RepoBuilder::new();
     builder.fetch_options(fetch_options);
 
     if let Some(branch) = &github_config.branch {
-        builder.branch(&branch);
+        builder.branch(branch);
     }
 
-    let repo_url = &resolve_github_url(&github_config.url);
+    let repo_url = &resolve_repo_url(&github_config.url);
 
     let repo = builder.clone(repo_url, to_path)?;
 
     if let Some(hash) = &github_config.hash {
-        let obj = repo.revparse_single(&hash)?;
+        let obj = repo.revparse_single(hash)?;
         repo.reset(&obj, git2::ResetType::Hard, None)?;
     } else if let Some(tag) = &github_config.tag {
-        let obj = repo.revparse_single(&tag)?;
+        let obj = repo.revparse_single(tag)?;
         repo.reset(&obj, git2::ResetType::Hard, None)?;
     }
 
@@ -60,6 +42,7 @@ pub fn clone_and_init_submodules(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::find_id_rsa;
     use std::fs;
     use std::path::PathBuf;
 
@@ -2,7 +2,7 @@ use std::{error::Error, path::Path};
 
 use lua_workspace_config::workspace_config::GithubDependency;
 
-use crate::resolve_github_url;
+use crate::resolve_repo_url;
 
 pub fn get_dep_from_repo(repo_path: &Path, url: &str) -> Result<GithubDependency, Box<dyn Error>> {
     let repo = git2::Repository::open(repo_path)?;
@@ -18,7 +18,7 @@ pub fn get_dep_from_repo(repo_path: &Path, url: &str) -> Result<GithubDependency
 
     let remote = repo.find_remote("origin")?;
     let url = remote.url().unwrap();
-    dep.url = resolve_github_url(url);
+    dep.url = resolve_repo_url(url);
 
     let head_commit = repo.head()?.peel_to_commit()?;
     dep.hash = Some(head_commit.id().to_string());
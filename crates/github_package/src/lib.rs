@@ -1,12 +1,21 @@
+pub mod cache;
+pub mod checksum;
 pub mod clone;
 pub mod check;
 pub mod update;
 pub mod dep;
+pub mod tag;
+pub mod url;
+
+use std::path::{Path, PathBuf};
 
 use dirs::home_dir;
+use git2::{Cred, RemoteCallbacks};
 pub use clone::clone_and_init_submodules;
 pub use check::check_github_repo_version;
+pub use tag::{find_best_tag, RemoteTag};
 pub use update::*;
+pub use url::{expand_host_alias, normalize_repo_url, parse_repo_url, resolve_repo_url};
 
 
 pub fn find_id_rsa() -> Option<String> {
@@ -19,10 +28,31 @@ pub fn find_id_rsa() -> Option<String> {
     }
 }
 
-pub fn resolve_github_url(url: &str) -> String {
-    if url.starts_with("https://") || url.starts_with("git@github.com") {
-        url.to_string()
+/// Root directory for luap's user-level data, `$LUAP_HOME` or `~/.luap`.
+pub fn luap_home() -> PathBuf {
+    if let Some(home) = std::env::var_os("LUAP_HOME") {
+        return PathBuf::from(home);
+    }
+    home_dir().unwrap_or_default().join(".luap")
+}
+
+pub(crate) fn remote_callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let ssh_id_rsa = find_id_rsa();
+
+    if let Some(ssh_id_rsa) = ssh_id_rsa {
+        callbacks.credentials(move |_url, username_from_url, _allowed_types| {
+            Cred::ssh_key(
+                username_from_url.unwrap(),
+                None,
+                Path::new(&ssh_id_rsa),
+                None,
+            )
+        });
     } else {
-        format!("https://github.com/{}", url)
+        callbacks.credentials(|_url, username_from_url, _allowed_types| {
+            Cred::ssh_key_from_agent(username_from_url.unwrap())
+        });
     }
+    callbacks
 }
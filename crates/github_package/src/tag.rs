@@ -0,0 +1,93 @@
+use git2::Error;
+use semver::{Version, VersionReq};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteTag {
+    pub name: String,
+    pub commit: String,
+}
+
+/// Parses a git tag as a semver version. A leading `v` is allowed and missing
+/// minor/patch components are treated as zero, so `v1.2` reads as `1.2.0`.
+pub fn parse_tag_version(tag: &str) -> Option<Version> {
+    let version = tag
+        .strip_prefix('v')
+        .or_else(|| tag.strip_prefix('V'))
+        .unwrap_or(tag);
+    if let Ok(version) = Version::parse(version) {
+        return Some(version);
+    }
+
+    let (core, rest) = match version.find(['-', '+']) {
+        Some(pos) => version.split_at(pos),
+        None => (version, ""),
+    };
+    let parts = core.split('.').count();
+    if parts == 0 || parts > 2 {
+        return None;
+    }
+    let padded = format!("{}{}{}", core, ".0".repeat(3 - parts), rest);
+    Version::parse(&padded).ok()
+}
+
+pub fn parse_version_req(req: &str) -> Result<VersionReq, Error> {
+    VersionReq::parse(req)
+        .map_err(|e| Error::from_str(&format!("invalid version requirement `{}`: {}", req, e)))
+}
+
+/// Returns the tag with the highest version that satisfies `req`.
+pub fn find_best_tag<'a>(tags: &'a [RemoteTag], req: &VersionReq) -> Option<&'a RemoteTag> {
+    tags.iter()
+        .filter_map(|tag| parse_tag_version(&tag.name).map(|version| (version, tag)))
+        .filter(|(version, _)| req.matches(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> RemoteTag {
+        RemoteTag {
+            name: name.to_string(),
+            commit: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_tag_version() {
+        assert_eq!(parse_tag_version("v1.2.3"), Some(Version::new(1, 2, 3)));
+        assert_eq!(parse_tag_version("1.2.3"), Some(Version::new(1, 2, 3)));
+        assert_eq!(parse_tag_version("v1.2"), Some(Version::new(1, 2, 0)));
+        assert_eq!(parse_tag_version("2"), Some(Version::new(2, 0, 0)));
+        assert_eq!(
+            parse_tag_version("v1.0-rc1"),
+            Some(Version::parse("1.0.0-rc1").unwrap())
+        );
+        assert_eq!(parse_tag_version("release-1"), None);
+        assert_eq!(parse_tag_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_find_best_tag() {
+        let tags = vec![
+            tag("v0.3.1"),
+            tag("v0.3.4"),
+            tag("v1.2.0"),
+            tag("v1.9.2"),
+            tag("v2.0.0"),
+            tag("v2.1.0-beta"),
+            tag("nightly"),
+        ];
+
+        let best = |req: &str| {
+            find_best_tag(&tags, &parse_version_req(req).unwrap()).map(|tag| tag.name.as_str())
+        };
+        assert_eq!(best("^1.2"), Some("v1.9.2"));
+        assert_eq!(best("~0.3"), Some("v0.3.4"));
+        assert_eq!(best(">=2, <3"), Some("v2.0.0"));
+        assert_eq!(best("^3"), None);
+        assert!(parse_version_req("not a version").is_err());
+    }
+}
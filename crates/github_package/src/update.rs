@@ -13,10 +13,10 @@ pub fn update_to_special_version(
 
     if let Some(branch) = &github_config.branch {
         if head.shorthand() != Some(branch) {
-            let (object, reference) = repo.revparse_ext(&branch)?;
+            let (object, reference) = repo.revparse_ext(branch)?;
             repo.checkout_tree(&object, None)?;
             if let Some(reference) = reference {
-                repo.set_head(&reference.name().unwrap())?;
+                repo.set_head(reference.name().unwrap())?;
             } else {
                 repo.set_head_detached(object.id())?;
             }
@@ -25,9 +25,9 @@ pub fn update_to_special_version(
 
     for i in 0..1 {
         let obj = if let Some(hash) = &github_config.hash {
-            repo.revparse_single(&hash)?
+            repo.revparse_single(hash)?
         } else if let Some(tag) = &github_config.tag {
-            repo.revparse_single(&tag)?
+            repo.revparse_single(tag)?
         } else {
             return Err(Error::from_str("No hash or tag provided"));
         };
@@ -41,10 +41,10 @@ pub fn update_to_special_version(
                         let branch = head.shorthand().unwrap();
 
                         repo.find_remote("origin")?.fetch(&[branch], None, None)?;
-                        let (object, reference) = repo.revparse_ext(&branch)?;
+                        let (object, reference) = repo.revparse_ext(branch)?;
                         repo.checkout_tree(&object, None)?;
                         if let Some(reference) = reference {
-                            repo.set_head(&reference.name().unwrap())?;
+                            repo.set_head(reference.name().unwrap())?;
                         } else {
                             repo.set_head_detached(object.id())?;
                         }
@@ -71,10 +71,10 @@ pub fn update_to_latest(repo_path: &Path) -> Result<(), Error> {
 
     repo.find_remote("origin")?.fetch(&[branch], None, None)?;
 
-    let (object, reference) = repo.revparse_ext(&branch)?;
+    let (object, reference) = repo.revparse_ext(branch)?;
     repo.checkout_tree(&object, None)?;
     if let Some(reference) = reference {
-        repo.set_head(&reference.name().unwrap())?;
+        repo.set_head(reference.name().unwrap())?;
     } else {
         repo.set_head_detached(object.id())?;
     }
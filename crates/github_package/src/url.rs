@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use dirs::home_dir;
+
+const SCHEMES: &[&str] = &["https://", "http://", "ssh://", "git://", "git+ssh://", "file://"];
+
+/// Hosts that can be written as `<prefix>:owner/repo`.
+const SHORTHANDS: &[(&str, &str)] = &[
+    ("gh", "https://github.com/"),
+    ("github", "https://github.com/"),
+    ("gitlab", "https://gitlab.com/"),
+    ("codeberg", "https://codeberg.org/"),
+];
+
+/// Turns the way a repository is written in package.toml into a url git
+/// understands:
+///
+/// - urls with a scheme (`https://`, `ssh://`, `git://`, `file://`, ...) and
+///   scp-like `user@host:path` are kept as they are,
+/// - `gh:`, `github:`, `gitlab:` and `codeberg:` expand to their https url,
+/// - paths (`/srv/git/json`, `./mirrors/json`, `~/git/json`, `C:\git\json`)
+///   become `file://` urls, relative ones are taken from the current directory,
+/// - `owner/repo` is a GitHub repository.
+pub fn parse_repo_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("empty repository url".to_string());
+    }
+
+    if let Some(scheme) = SCHEMES.iter().find(|scheme| url.starts_with(**scheme)) {
+        if url.len() == scheme.len() {
+            return Err(format!("`{}` has nothing after the scheme", url));
+        }
+        return Ok(url.to_string());
+    }
+    if url.contains("://") {
+        return Err(format!("`{}` uses an unsupported url scheme", url));
+    }
+
+    if let Some(path) = local_path(url) {
+        return Ok(file_url(&path));
+    }
+
+    if let Some((prefix, rest)) = url.split_once(':') {
+        if let Some((_, base)) = SHORTHANDS.iter().find(|(name, _)| *name == prefix) {
+            if rest.is_empty() {
+                return Err(format!("`{}` names no repository", url));
+            }
+            return Ok(format!("{}{}", base, rest.trim_start_matches('/')));
+        }
+        // scp-like syntax, the host part has a user or a domain
+        if prefix.contains('@') || prefix.contains('.') {
+            if rest.is_empty() {
+                return Err(format!("`{}` names no repository", url));
+            }
+            return Ok(url.to_string());
+        }
+        return Err(format!("unknown host alias `{}` in `{}`", prefix, url));
+    }
+
+    if url.split('/').filter(|part| !part.is_empty()).count() >= 2 {
+        return Ok(format!("https://github.com/{}", url));
+    }
+    Err(format!("`{}` is not a repository url", url))
+}
+
+/// Like [`parse_repo_url`], but hands urls it does not understand to git as they
+/// are, which reports its own error. Manifests are validated before this is
+/// reached.
+pub fn resolve_repo_url(url: &str) -> String {
+    parse_repo_url(url).unwrap_or_else(|_| url.to_string())
+}
+
+/// Expands a `<alias>:path` url with the `[hosts]` table of a manifest, e.g.
+/// `ghe = "https://github.example.com"` turns `ghe:team/json` into
+/// `https://github.example.com/team/json`.
+pub fn expand_host_alias(url: &str, hosts: &HashMap<String, String>) -> String {
+    let Some((prefix, rest)) = url.split_once(':') else {
+        return url.to_string();
+    };
+    if rest.starts_with("//") {
+        return url.to_string();
+    }
+    match hosts.get(prefix) {
+        Some(base) if base.ends_with('/') || base.ends_with(':') => format!("{}{}", base, rest),
+        Some(base) => format!("{}/{}", base, rest.trim_start_matches('/')),
+        None => url.to_string(),
+    }
+}
+
+/// Normalizes a dependency url so that two spellings of the same repository
+/// compare equal.
+pub fn normalize_repo_url(url: &str) -> String {
+    let url = resolve_repo_url(url);
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+
+    // `git@host:path` is `ssh://git@host/path`
+    let url = match url.split_once(':') {
+        Some((host, path)) if !url.contains("://") => format!("ssh://{}/{}", host, path),
+        _ => url.to_string(),
+    };
+
+    // scheme and host are case insensitive, the path is not
+    match url.split_once("://") {
+        Some((scheme, rest)) if scheme != "file" => {
+            let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+            format!(
+                "{}://{}/{}",
+                scheme.to_ascii_lowercase(),
+                host.to_ascii_lowercase(),
+                path
+            )
+        }
+        _ => url,
+    }
+}
+
+fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(rest) = url.strip_prefix("~/") {
+        return Some(home_dir()?.join(rest));
+    }
+
+    let bytes = url.as_bytes();
+    let windows_drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    if url.starts_with('/')
+        || url.starts_with("./")
+        || url.starts_with("../")
+        || url.starts_with(".\\")
+        || url.starts_with("..\\")
+        || windows_drive
+    {
+        let path = Path::new(url);
+        // a drive letter is absolute whatever the platform luap runs on
+        if path.is_absolute() || windows_drive {
+            return Some(path.to_path_buf());
+        }
+        return Some(std::env::current_dir().ok()?.join(path));
+    }
+    None
+}
+
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_url() {
+        let parse = |url: &str| parse_repo_url(url).unwrap();
+        assert_eq!(parse("rxi/json.lua"), "https://github.com/rxi/json.lua");
+        assert_eq!(parse("gh:rxi/json.lua"), "https://github.com/rxi/json.lua");
+        assert_eq!(parse("gitlab:group/sub/lib"), "https://gitlab.com/group/sub/lib");
+        assert_eq!(parse("codeberg:owner/lib"), "https://codeberg.org/owner/lib");
+        assert_eq!(
+            parse("git@gitlab.com:group/lib.git"),
+            "git@gitlab.com:group/lib.git"
+        );
+        assert_eq!(
+            parse("ssh://git@git.example.com:2222/lib.git"),
+            "ssh://git@git.example.com:2222/lib.git"
+        );
+        assert_eq!(parse("git://example.com/lib"), "git://example.com/lib");
+        assert_eq!(parse("file:///srv/git/lib.git"), "file:///srv/git/lib.git");
+        assert_eq!(parse("/srv/git/lib.git"), "file:///srv/git/lib.git");
+        assert_eq!(parse("C:\\git\\lib"), "file:///C:/git/lib");
+        assert!(parse("./mirrors/lib").starts_with("file:///"));
+        assert!(parse("./mirrors/lib").ends_with("/mirrors/lib"));
+
+        assert!(parse_repo_url("ghe:team/lib").is_err());
+        assert!(parse_repo_url("ftp://example.com/lib").is_err());
+        assert!(parse_repo_url("json").is_err());
+        assert!(parse_repo_url("gh:").is_err());
+        assert!(parse_repo_url("").is_err());
+    }
+
+    #[test]
+    fn test_expand_host_alias() {
+        let hosts: HashMap<String, String> = [
+            ("ghe", "https://github.example.com"),
+            ("corp", "git@git.corp.example.com:"),
+        ]
+        .into_iter()
+        .map(|(name, base)| (name.to_string(), base.to_string()))
+        .collect();
+
+        assert_eq!(
+            expand_host_alias("ghe:team/lib", &hosts),
+            "https://github.example.com/team/lib"
+        );
+        assert_eq!(
+            expand_host_alias("corp:team/lib", &hosts),
+            "git@git.corp.example.com:team/lib"
+        );
+        assert_eq!(expand_host_alias("gh:rxi/json.lua", &hosts), "gh:rxi/json.lua");
+        assert_eq!(
+            expand_host_alias("https://example.com/lib", &hosts),
+            "https://example.com/lib"
+        );
+    }
+
+    #[test]
+    fn test_normalize_repo_url() {
+        assert_eq!(
+            normalize_repo_url("https://GitHub.com/rxi/json.lua.git/"),
+            "https://github.com/rxi/json.lua"
+        );
+        assert_eq!(
+            normalize_repo_url("gh:rxi/json.lua"),
+            normalize_repo_url("rxi/json.lua")
+        );
+        assert_eq!(
+            normalize_repo_url("git@gitlab.com:group/lib.git"),
+            normalize_repo_url("ssh://git@gitlab.com/group/lib")
+        );
+        assert_ne!(
+            normalize_repo_url("https://github.com/Owner/Lib"),
+            normalize_repo_url("https://github.com/owner/lib")
+        );
+    }
+}
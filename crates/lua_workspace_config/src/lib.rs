@@ -1,2 +1,4 @@
+pub mod manifest_edit;
+pub mod schema;
 pub mod workspace_config;
 pub mod workspace_lock;
\ No newline at end of file
@@ -0,0 +1,386 @@
+use toml_edit::{DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::workspace_config::Dependency;
+
+pub const DEPENDENCIES: &[&str] = &["dependencies"];
+pub const DEV_DEPENDENCIES: &[&str] = &["dev-dependencies"];
+pub const WORKSPACE_DEPENDENCIES: &[&str] = &["workspace", "dependencies"];
+
+/// Edits package.toml in place. Only the keys that are touched change, comments,
+/// key order and formatting of everything else are kept as they were.
+pub struct ManifestEditor {
+    doc: DocumentMut,
+}
+
+impl ManifestEditor {
+    pub fn parse_toml_str(toml: &str) -> Result<Self, toml_edit::TomlError> {
+        Ok(Self { doc: toml.parse()? })
+    }
+
+    pub fn parse_toml_file(file_path: &str) -> Result<Self, std::io::Error> {
+        let toml_str = std::fs::read_to_string(file_path)?;
+        Self::parse_toml_str(&toml_str).map_err(std::io::Error::other)
+    }
+
+    pub fn write_toml_file(&self, file_path: &str) -> Result<(), std::io::Error> {
+        std::fs::write(file_path, self.doc.to_string())
+    }
+
+    /// Adds or replaces `name` in the dependency table at `table`, e.g.
+    /// [`DEPENDENCIES`]. An existing entry keeps its comments and its style,
+    /// a `[dependencies.name]` table stays a table.
+    pub fn set_dependency(&mut self, table: &[&str], name: &str, dep: &Dependency) {
+        let (deps, inline) = self.table_mut(table);
+        match deps.get_mut(name) {
+            Some(item) if item.is_table_like() => {
+                let inline = item.is_inline_table();
+                write_fields(item.as_table_like_mut().unwrap(), dep, inline);
+            }
+            Some(Item::Value(old)) => {
+                let decor = old.decor().clone();
+                *old = dependency_value(dep);
+                *old.decor_mut() = decor;
+            }
+            _ => {
+                insert_value(deps, name, dependency_value(dep), inline);
+            }
+        }
+    }
+
+    /// Removes `name` from the dependency table at `table`, returns whether it
+    /// was there.
+    pub fn remove_dependency(&mut self, table: &[&str], name: &str) -> bool {
+        let mut item = self.doc.as_item_mut();
+        for key in table {
+            match item.get_mut(key) {
+                Some(next) => item = next,
+                None => return false,
+            }
+        }
+        item.as_table_like_mut()
+            .map(|deps| deps.remove(name).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Walks to the table at `path`, creating what is missing. Tables written
+    /// inline stay inline, everything else becomes a `[table]` header. Also
+    /// returns whether the table is an inline one.
+    fn table_mut(&mut self, path: &[&str]) -> (&mut dyn TableLike, bool) {
+        let mut table: &mut dyn TableLike = self.doc.as_table_mut();
+        let mut inline = false;
+        for (i, key) in path.iter().enumerate() {
+            let last = i + 1 == path.len();
+            let parent = table;
+            if !parent.get(key).is_some_and(Item::is_table_like) {
+                let item = if inline {
+                    Item::Value(Value::InlineTable(InlineTable::new()))
+                } else {
+                    let mut new_table = Table::new();
+                    new_table.set_implicit(true);
+                    Item::Table(new_table)
+                };
+                parent.insert(key, item);
+            }
+
+            let item = parent.get_mut(key).unwrap();
+            match item {
+                // the last table is where keys go, it has to be written out
+                Item::Table(t) if last => t.set_implicit(false),
+                Item::Value(Value::InlineTable(_)) => inline = true,
+                _ => {}
+            }
+            table = item.as_table_like_mut().unwrap();
+        }
+        (table, inline)
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        self.doc.to_string()
+    }
+}
+
+fn dependency_fields(dep: &Dependency) -> Vec<(&'static str, Option<String>)> {
+    match dep {
+        Dependency::Simple(url) => vec![
+            ("url", Some(url.clone())),
+            ("tag", None),
+            ("branch", None),
+            ("hash", None),
+            ("version", None),
+            ("path", None),
+            ("archive", None),
+            ("sha256", None),
+            ("strip-prefix", None),
+        ],
+        Dependency::Detailed {
+            url,
+            tag,
+            branch,
+            hash,
+            version,
+            path,
+            archive,
+            sha256,
+            strip_prefix,
+            // luap never writes these, existing ones are left as written
+            rock: _,
+            optional: _,
+            features: _,
+            package: _,
+        } => vec![
+            ("url", url.clone()),
+            ("tag", tag.clone()),
+            ("branch", branch.clone()),
+            ("hash", hash.clone()),
+            ("version", version.clone()),
+            ("path", path.clone()),
+            ("archive", archive.clone()),
+            ("sha256", sha256.clone()),
+            ("strip-prefix", strip_prefix.clone()),
+        ],
+        // the source and revision are the ones of the workspace
+        Dependency::Workspace { .. } => vec![
+            ("url", None),
+            ("tag", None),
+            ("branch", None),
+            ("hash", None),
+            ("version", None),
+            ("path", None),
+            ("archive", None),
+            ("sha256", None),
+            ("strip-prefix", None),
+        ],
+    }
+}
+
+fn dependency_value(dep: &Dependency) -> Value {
+    if let Dependency::Simple(url) = dep {
+        return Value::from(url.as_str());
+    }
+
+    let mut table = InlineTable::new();
+    write_fields(&mut table, dep, true);
+    Value::InlineTable(table)
+}
+
+fn write_fields(table: &mut dyn TableLike, dep: &Dependency, inline: bool) {
+    let mut fields: Vec<(&str, Option<Value>)> = dependency_fields(dep)
+        .into_iter()
+        .map(|(key, field)| (key, field.map(Value::from)))
+        .collect();
+    fields.push(("workspace", dep.is_workspace().then(|| Value::from(true))));
+    for (key, field) in fields {
+        match field {
+            Some(field) => match table.get_mut(key).and_then(Item::as_value_mut) {
+                Some(old) => {
+                    let decor = old.decor().clone();
+                    *old = field;
+                    *old.decor_mut() = decor;
+                }
+                None => insert_value(table, key, field, inline),
+            },
+            None => {
+                table.remove(key);
+            }
+        }
+    }
+}
+
+fn insert_value(table: &mut dyn TableLike, key: &str, mut new_value: Value, inline: bool) {
+    if inline {
+        // the space before `}` belongs to the last entry, it moves to the new one
+        if let Some((_, Item::Value(last))) = table.iter_mut().last() {
+            if let Some(suffix) = last.decor().suffix().cloned() {
+                last.decor_mut().set_suffix("");
+                new_value.decor_mut().set_suffix(suffix);
+            }
+        }
+    }
+    table.insert(key, Item::Value(new_value));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANIFEST: &str = r#"# the demo app
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+# v2 changed the encoder api
+json = { url = "https://github.com/rxi/json.lua", tag = "v0.1.2" } # keep
+say = "https://github.com/lunarmodules/say"
+
+[dependencies.lpeg]
+url = "https://github.com/roberto-ieru/LPeg"
+branch = "master" # tracks upstream
+
+[dev-dependencies]
+"#;
+
+    fn detailed(url: &str, tag: Option<&str>) -> Dependency {
+        Dependency::Detailed {
+            url: Some(url.to_string()),
+            tag: tag.map(|t| t.to_string()),
+            branch: None,
+            hash: None,
+            version: None,
+            path: None,
+            archive: None,
+            sha256: None,
+            strip_prefix: None,
+            rock: None,
+            optional: None,
+            features: None,
+            package: None,
+        }
+    }
+
+    #[test]
+    fn test_set_dependency_keeps_the_rest_of_the_document() {
+        let mut editor = ManifestEditor::parse_toml_str(MANIFEST).unwrap();
+        editor.set_dependency(
+            DEPENDENCIES,
+            "json",
+            &detailed("https://github.com/rxi/json.lua", Some("v0.1.3")),
+        );
+        let expected = MANIFEST.replace("v0.1.2", "v0.1.3");
+        assert_eq!(editor.to_toml_string(), expected);
+
+        editor.set_dependency(
+            DEPENDENCIES,
+            "lpeg",
+            &detailed("https://github.com/roberto-ieru/LPeg", Some("1.1.0")),
+        );
+        let expected = expected.replace(
+            "branch = \"master\" # tracks upstream\n",
+            "tag = \"1.1.0\"\n",
+        );
+        assert_eq!(editor.to_toml_string(), expected);
+    }
+
+    #[test]
+    fn test_set_dependency_appends_new_entries() {
+        let mut editor = ManifestEditor::parse_toml_str(MANIFEST).unwrap();
+        editor.set_dependency(
+            DEV_DEPENDENCIES,
+            "busted",
+            &Dependency::Simple("https://github.com/lunarmodules/busted".to_string()),
+        );
+        let expected = format!(
+            "{}busted = \"https://github.com/lunarmodules/busted\"\n",
+            MANIFEST
+        );
+        assert_eq!(editor.to_toml_string(), expected);
+
+        let mut editor = ManifestEditor::parse_toml_str("[package]\nname = \"app\"\n").unwrap();
+        editor.set_dependency(
+            DEPENDENCIES,
+            "json",
+            &detailed("rxi/json.lua", Some("v0.1.2")),
+        );
+        assert_eq!(
+            editor.to_toml_string(),
+            "[package]\nname = \"app\"\n\n[dependencies]\njson = { url = \"rxi/json.lua\", tag = \"v0.1.2\" }\n"
+        );
+    }
+
+    #[test]
+    fn test_set_dependency_keeps_inline_tables_inline() {
+        let manifest =
+            "dependencies = { say = \"lunarmodules/say\" } # inline\n\n[package]\nname = \"app\"\n";
+        let mut editor = ManifestEditor::parse_toml_str(manifest).unwrap();
+        editor.set_dependency(
+            DEPENDENCIES,
+            "json",
+            &Dependency::Simple("rxi/json.lua".to_string()),
+        );
+        assert_eq!(
+            editor.to_toml_string(),
+            "dependencies = { say = \"lunarmodules/say\", json = \"rxi/json.lua\" } # inline\n\n[package]\nname = \"app\"\n"
+        );
+
+        let manifest =
+            "[dependencies]\njson = { url = \"rxi/json.lua\", version = \"^1\" } # semver\n";
+        let mut editor = ManifestEditor::parse_toml_str(manifest).unwrap();
+        editor.set_dependency(
+            DEPENDENCIES,
+            "json",
+            &Dependency::Detailed {
+                url: Some("rxi/json.lua".to_string()),
+                tag: Some("v1.2.0".to_string()),
+                branch: None,
+                hash: None,
+                version: Some("^1".to_string()),
+                path: None,
+                archive: None,
+                sha256: None,
+                strip_prefix: None,
+                rock: None,
+                optional: None,
+                features: None,
+                package: None,
+            },
+        );
+        assert_eq!(
+            editor.to_toml_string(),
+            "[dependencies]\njson = { url = \"rxi/json.lua\", version = \"^1\", tag = \"v1.2.0\" } # semver\n"
+        );
+
+        let manifest = "dependencies = { say = \"lunarmodules/say\" }\n";
+        let mut editor = ManifestEditor::parse_toml_str(manifest).unwrap();
+        editor.set_dependency(
+            DEPENDENCIES,
+            "say",
+            &Dependency::Simple("olivine-labs/say".to_string()),
+        );
+        assert!(editor.remove_dependency(DEPENDENCIES, "say"));
+        assert_eq!(editor.to_toml_string(), "dependencies = {}\n");
+    }
+
+    #[test]
+    fn test_set_workspace_dependency() {
+        let mut editor = ManifestEditor::parse_toml_str(MANIFEST).unwrap();
+        let inherit = Dependency::Workspace {
+            workspace: true,
+            optional: None,
+            features: None,
+        };
+        editor.set_dependency(DEPENDENCIES, "json", &inherit);
+        editor.set_dependency(DEV_DEPENDENCIES, "busted", &inherit);
+        let expected = MANIFEST.replace(
+            "{ url = \"https://github.com/rxi/json.lua\", tag = \"v0.1.2\" } # keep",
+            "{ workspace = true } # keep",
+        );
+        assert_eq!(
+            editor.to_toml_string(),
+            format!("{}busted = {{ workspace = true }}\n", expected)
+        );
+
+        let mut editor =
+            ManifestEditor::parse_toml_str("[workspace]\nmembers = [\"packages/*\"]\n").unwrap();
+        editor.set_dependency(
+            WORKSPACE_DEPENDENCIES,
+            "json",
+            &detailed("rxi/json.lua", Some("v2.1")),
+        );
+        assert_eq!(
+            editor.to_toml_string(),
+            "[workspace]\nmembers = [\"packages/*\"]\n\n[workspace.dependencies]\njson = { url = \"rxi/json.lua\", tag = \"v2.1\" }\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_dependency_keeps_the_rest_of_the_document() {
+        let mut editor = ManifestEditor::parse_toml_str(MANIFEST).unwrap();
+        assert!(editor.remove_dependency(DEPENDENCIES, "json"));
+        assert!(!editor.remove_dependency(DEV_DEPENDENCIES, "json"));
+        let expected = MANIFEST.replace(
+            "# v2 changed the encoder api\njson = { url = \"https://github.com/rxi/json.lua\", tag = \"v0.1.2\" } # keep\n",
+            "",
+        );
+        assert_eq!(editor.to_toml_string(), expected);
+    }
+}
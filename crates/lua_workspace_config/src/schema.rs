@@ -0,0 +1,50 @@
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+use crate::workspace_config::WorkspaceConfig;
+use crate::workspace_lock::WorkspaceLock;
+
+/// JSON Schema of package.toml, generated from the types it is parsed into.
+pub fn manifest_schema() -> RootSchema {
+    let mut schema = schema_for!(WorkspaceConfig);
+    let metadata = schema.schema.metadata();
+    metadata.title = Some("package.toml".to_string());
+    metadata.description = Some("Manifest of a luap package".to_string());
+    schema
+}
+
+/// JSON Schema of package.lock, generated from the types it is parsed into.
+pub fn lock_schema() -> RootSchema {
+    let mut schema = schema_for!(WorkspaceLock);
+    let metadata = schema.schema.metadata();
+    metadata.title = Some("package.lock".to_string());
+    metadata.description = Some("Lock file written by luap install".to_string());
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_schema() {
+        let schema = manifest_schema();
+        let properties = &schema.schema.object.as_ref().unwrap().properties;
+        for key in [
+            "package",
+            "dependencies",
+            "dev-dependencies",
+            "target",
+            "patch",
+        ] {
+            assert!(properties.contains_key(key), "missing {}", key);
+        }
+        assert!(schema.definitions.contains_key("Dependency"));
+
+        let schema = lock_schema();
+        let properties = &schema.schema.object.as_ref().unwrap().properties;
+        assert!(properties.contains_key("version"));
+        assert!(properties.contains_key("package"));
+        assert!(schema.definitions.contains_key("LockedPackage"));
+    }
+}
@@ -1,36 +1,235 @@
 use std::collections::HashMap;
 
-use serde::{Deserialize, Serialize};
-
-use crate::workspace_lock::WorkspaceLock;
+use schemars::JsonSchema;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 // but file name is package.toml
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct WorkspaceConfig {
     pub package: Option<Package>,
     pub dependencies: Option<HashMap<String, Dependency>>,
     #[serde(rename = "dev-dependencies")]
     pub dev_dependencies: Option<HashMap<String, Dependency>>,
+    /// Host aliases, `ghe = "https://github.example.com"` lets dependencies
+    /// be written as `ghe:team/repo`.
+    pub hosts: Option<HashMap<String, String>>,
+    pub rocks: Option<RocksConfig>,
+    /// Feature names mapped to the optional dependencies and other features
+    /// they enable.
+    pub features: Option<HashMap<String, Vec<String>>>,
+    /// Dependencies that are only installed for one Lua runtime, keyed by
+    /// target name such as `luajit` or `lua5.4`.
+    pub target: Option<HashMap<String, TargetConfig>>,
+    /// Git urls mapped to the source that replaces them anywhere in the graph,
+    /// only read from the root package.toml.
+    pub patch: Option<HashMap<String, Dependency>>,
+    /// Project tasks run by `luap run <name>`, each one a shell command.
+    pub scripts: Option<HashMap<String, String>>,
+    pub build: Option<BuildConfig>,
+    pub test: Option<TestConfig>,
+    pub install: Option<InstallConfig>,
+    /// Commands the package provides, each one a Lua script relative to the
+    /// package, linked into `$LUAP_HOME/bin` by `luap install -g`.
+    pub bin: Option<HashMap<String, String>>,
+    pub workspace: Option<WorkspaceTable>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct TargetConfig {
+    pub dependencies: Option<HashMap<String, Dependency>>,
+    #[serde(rename = "dev-dependencies")]
+    pub dev_dependencies: Option<HashMap<String, Dependency>>,
+}
+
+/// Lua runtimes a project can target, and the Lua version each one runs.
+pub const TARGETS: &[(&str, &str)] = &[
+    ("lua5.1", "5.1"),
+    ("lua5.2", "5.2"),
+    ("lua5.3", "5.3"),
+    ("lua5.4", "5.4"),
+    ("luajit", "5.1"),
+];
+
+pub fn target_lua_version(target: &str) -> Option<&'static str> {
+    TARGETS
+        .iter()
+        .find(|(name, _)| *name == target)
+        .map(|(_, version)| *version)
+}
+
+/// C modules of the package, laid out like the `builtin` build type of
+/// LuaRocks.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct BuildConfig {
+    /// Keyed by module name, `socket.core` is built as `socket/core.so`.
+    pub modules: Option<HashMap<String, ModuleBuild>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct ModuleBuild {
+    /// C sources, relative to the package.
+    pub sources: Vec<String>,
+    /// Include directories, relative to the package.
+    pub incdirs: Option<Vec<String>>,
+    /// Preprocessor definitions, `NAME` or `NAME=value`.
+    pub defines: Option<Vec<String>>,
+    /// Libraries to link, without the `lib` prefix.
+    pub libraries: Option<Vec<String>>,
+    pub libdirs: Option<Vec<String>>,
+}
+
+/// How `luap test` runs the tests.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct TestConfig {
+    /// The test runner command, `busted` by default.
+    pub runner: Option<String>,
+    /// Arguments always passed to the runner.
+    pub args: Option<Vec<String>>,
+    /// The option of the runner that filters tests by name, `--filter` for
+    /// busted. Without it, filters are passed as plain arguments.
+    pub filter: Option<String>,
+}
+
+/// Where and how `luap install` lays out the packages.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct InstallConfig {
+    /// The directory packages are installed in, relative to the project,
+    /// `lua_modules` by default.
+    pub dir: Option<String>,
+    pub layout: Option<InstallLayout>,
+}
+
+pub const DEFAULT_INSTALL_DIR: &str = "lua_modules";
+
+/// Packages of one repository that are installed together, into the
+/// `package.lock` and install directory of the root.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct WorkspaceTable {
+    /// Directories of the members relative to the root, a `*` matches any
+    /// name as in `packages/*`.
+    pub members: Option<Vec<String>>,
+    /// Dependencies declared once for every member, a member uses one with
+    /// `name = { workspace = true }`.
+    pub dependencies: Option<HashMap<String, Dependency>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallLayout {
+    /// Every package in a directory of its own, `<dir>/<name>`.
+    #[default]
+    Nested,
+    /// The modules of every package merged into `<dir>`.
+    Flat,
+    /// A LuaRocks tree, modules in `<dir>/share/lua/<version>` and C modules
+    /// in `<dir>/lib/lua/<version>`.
+    Rocks,
+}
+
+impl InstallLayout {
+    pub const NAMES: &'static [&'static str] = &["nested", "flat", "rocks"];
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct RocksConfig {
+    /// A rocks server url or a local directory laid out like one.
+    pub server: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Package {
     pub name: Option<String>,
     pub version: Option<String>,
     pub path: Option<String>,
+    /// Lua versions the package runs on, a range such as `>=5.1, <5.5`.
+    pub lua: Option<String>,
+    /// The target to install for when `--target` is not given.
+    #[serde(rename = "default-target")]
+    pub default_target: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
-#[serde(untagged)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone)]
+#[serde(untagged, remote = "Self")]
+#[allow(clippy::large_enum_variant)] // manifests hold a handful of these
 pub enum Dependency {
-    Simple(String /* url */),
+    /// A git url, or `owner/repo` on GitHub.
+    Simple(String),
+    /// The entry of the same name in `[workspace.dependencies]` of the
+    /// workspace root.
+    Workspace {
+        workspace: bool,
+        optional: Option<bool>,
+        /// Enabled on top of the features of the workspace entry.
+        features: Option<Vec<String>>,
+    },
     Detailed {
-        url: String,
+        /// Without a url, `path` names a local package that is used in place.
+        url: Option<String>,
         tag: Option<String>,
         branch: Option<String>,
         hash: Option<String>,
         version: Option<String>,
         path: Option<String>,
+        /// A release tarball or zip, downloaded or copied instead of cloned.
+        archive: Option<String>,
+        sha256: Option<String>,
+        #[serde(rename = "strip-prefix")]
+        strip_prefix: Option<String>,
+        /// A rock from the LuaRocks server.
+        rock: Option<RockRequirement>,
+        /// Only installed when a feature of the dependent enables it.
+        optional: Option<bool>,
+        /// Features of the dependency to enable.
+        features: Option<Vec<String>>,
+        /// The name of the package upstream, the key is then only the local
+        /// name it is installed and required as.
+        package: Option<String>,
+    },
+}
+
+/// Everything a member can set on a dependency it inherits.
+pub const INHERITED_KEYS: &[&str] = &["workspace", "optional", "features"];
+
+// untagged, `{ workspace = false }` or `{ workspace = true, url = ".." }` would
+// quietly become some other kind of dependency
+impl<'de> Deserialize<'de> for Dependency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = toml::Value::deserialize(deserializer)?;
+        if let Some(table) = value.as_table() {
+            if let Some(workspace) = table.get("workspace") {
+                if workspace.as_bool() != Some(true) {
+                    return Err(D::Error::custom("`workspace` must be true"));
+                }
+                if let Some(key) = table
+                    .keys()
+                    .find(|key| !INHERITED_KEYS.contains(&key.as_str()))
+                {
+                    return Err(D::Error::custom(format!(
+                        "`{}` is set by the workspace, it cannot be used with `workspace`",
+                        key
+                    )));
+                }
+            }
+        }
+        Dependency::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Dependency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Dependency::serialize(self, serializer)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum RockRequirement {
+    Name(String),
+    Detailed {
+        name: String,
+        /// A LuaRocks version, with or without its revision.
+        version: Option<String>,
     },
 }
 
@@ -43,6 +242,12 @@ impl Dependency {
                 branch: None,
                 hash: None,
             },
+            Dependency::Workspace { .. } => GithubDependency {
+                url: String::new(),
+                tag: None,
+                branch: None,
+                hash: None,
+            },
             Dependency::Detailed {
                 url,
                 tag,
@@ -50,7 +255,7 @@ impl Dependency {
                 hash,
                 ..
             } => GithubDependency {
-                url: url.clone(),
+                url: url.clone().unwrap_or_default(),
                 tag: tag.clone(),
                 branch: branch.clone(),
                 hash: hash.clone(),
@@ -60,73 +265,237 @@ impl Dependency {
 
     pub fn get_path(&self) -> Option<String> {
         match self {
-            Dependency::Simple(_) => None,
+            Dependency::Simple(_) | Dependency::Workspace { .. } => None,
             Dependency::Detailed { path, .. } => path.clone(),
         }
     }
 
     pub fn get_version(&self) -> Option<String> {
         match self {
-            Dependency::Simple(_) => None,
+            Dependency::Simple(_) | Dependency::Workspace { .. } => None,
             Dependency::Detailed { version, .. } => version.clone(),
         }
     }
 
-    pub fn get_url(&self) -> String {
+    /// The directory of a dependency that is used in place instead of being
+    /// cloned, relative to the package.toml that declares it.
+    pub fn get_local_path(&self) -> Option<String> {
         match self {
-            Dependency::Simple(url) => url.clone(),
-            Dependency::Detailed { url, .. } => url.clone(),
+            Dependency::Detailed {
+                url: None,
+                archive: None,
+                rock: None,
+                path,
+                ..
+            } => path.clone(),
+            _ => None,
+        }
+    }
+
+    /// The same dependency fetched from `url`.
+    pub fn with_url(&self, url: String) -> Dependency {
+        let mut dep = self.clone();
+        match &mut dep {
+            Dependency::Simple(old) => *old = url,
+            Dependency::Detailed { url: old, .. } => *old = Some(url),
+            Dependency::Workspace { .. } => {}
         }
+        dep
     }
 
-    pub fn try_merge_lock_dependency(&mut self, lock_dep: &Dependency) {
+    /// The same dependency found at `path`.
+    pub fn with_path(&self, path: String) -> Dependency {
+        let mut dep = self.clone();
+        if let Dependency::Detailed { path: old, .. } = &mut dep {
+            *old = Some(path);
+        }
+        dep
+    }
+
+    /// A local package used in place.
+    pub fn local(path: String) -> Dependency {
+        Dependency::Detailed {
+            url: None,
+            tag: None,
+            branch: None,
+            hash: None,
+            version: None,
+            path: Some(path),
+            archive: None,
+            sha256: None,
+            strip_prefix: None,
+            rock: None,
+            optional: None,
+            features: None,
+            package: None,
+        }
+    }
+
+    /// The same dependency unpacked from `archive`.
+    pub fn with_archive(&self, archive: String) -> Dependency {
+        let mut dep = self.clone();
+        if let Dependency::Detailed { archive: old, .. } = &mut dep {
+            *old = Some(archive);
+        }
+        dep
+    }
+
+    pub fn get_archive_dependency(&self) -> Option<ArchiveDependency> {
+        match self {
+            Dependency::Detailed {
+                archive: Some(archive),
+                sha256,
+                strip_prefix,
+                ..
+            } => Some(ArchiveDependency {
+                url: archive.clone(),
+                sha256: sha256.clone(),
+                strip_prefix: strip_prefix.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn get_rock_dependency(&self) -> Option<RockDependency> {
+        match self {
+            Dependency::Detailed {
+                rock: Some(RockRequirement::Name(name)),
+                ..
+            } => Some(RockDependency {
+                name: name.clone(),
+                version: None,
+            }),
+            Dependency::Detailed {
+                rock: Some(RockRequirement::Detailed { name, version }),
+                ..
+            } => Some(RockDependency {
+                name: name.clone(),
+                version: version.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        match self {
+            Dependency::Simple(_) => false,
+            Dependency::Detailed { optional, .. } | Dependency::Workspace { optional, .. } => {
+                optional.unwrap_or(false)
+            }
+        }
+    }
+
+    pub fn is_workspace(&self) -> bool {
+        matches!(self, Dependency::Workspace { .. })
+    }
+
+    /// This entry of `[workspace.dependencies]` as a member that inherits it
+    /// with `member` uses it, with the features of both and `optional` of the
+    /// member.
+    pub fn inherited_by(&self, member: &Dependency) -> Dependency {
+        let Dependency::Workspace {
+            optional, features, ..
+        } = member
+        else {
+            return member.clone();
+        };
+        let mut all = self.get_features();
+        for feature in features.iter().flatten() {
+            if !all.contains(feature) {
+                all.push(feature.clone());
+            }
+        }
+        let mut dep = match self {
+            Dependency::Simple(url) => Dependency::Detailed {
+                url: Some(url.clone()),
+                tag: None,
+                branch: None,
+                hash: None,
+                version: None,
+                path: None,
+                archive: None,
+                sha256: None,
+                strip_prefix: None,
+                rock: None,
+                optional: None,
+                features: None,
+                package: None,
+            },
+            dep => dep.clone(),
+        };
         if let Dependency::Detailed {
-            url: _,
-            tag,
-            branch,
-            hash,
-            version,
-            path,
-        } = lock_dep
+            optional: old_optional,
+            features: old_features,
+            ..
+        } = &mut dep
         {
-            match self {
-                Dependency::Simple(url) => {
-                    *self = Dependency::Detailed {
-                        url: url.clone(),
-                        tag: tag.clone(),
-                        branch: branch.clone(),
-                        hash: hash.clone(),
-                        version: version.clone(),
-                        path: path.clone(),
-                    }
-                }
-                Dependency::Detailed {
-                    url: _,
-                    tag: self_tag,
-                    branch: self_branch,
-                    hash: self_hash,
-                    version: self_version,
-                    path: self_path,
-                } => {
-                    if self_tag.is_none() {
-                        *self_tag = tag.clone();
-                    }
-                    if self_branch.is_none() {
-                        *self_branch = branch.clone();
-                    }
-                    if self_hash.is_none() {
-                        *self_hash = hash.clone();
-                    }
-                    if self_version.is_none() {
-                        *self_version = version.clone();
-                    }
-                    if self_path.is_none() {
-                        *self_path = path.clone();
-                    }
+            *old_optional = *optional;
+            *old_features = (!all.is_empty()).then_some(all);
+        }
+        dep
+    }
+
+    /// The source of `patch` with everything else, such as the features to
+    /// enable, kept from this dependency.
+    pub fn with_source(&self, patch: &Dependency) -> Dependency {
+        let features = match self {
+            Dependency::Simple(_) => None,
+            Dependency::Detailed { features, .. } | Dependency::Workspace { features, .. } => {
+                features.clone()
+            }
+        };
+        match (patch, features) {
+            (patch, None) => patch.clone(),
+            (Dependency::Simple(url), features) => Dependency::Detailed {
+                url: Some(url.clone()),
+                tag: None,
+                branch: None,
+                hash: None,
+                version: None,
+                path: None,
+                archive: None,
+                sha256: None,
+                strip_prefix: None,
+                rock: None,
+                optional: None,
+                features,
+                package: None,
+            },
+            // a patch names a source, it inherits nothing
+            (Dependency::Workspace { .. }, _) => patch.clone(),
+            (Dependency::Detailed { .. }, features) => {
+                let mut dep = patch.clone();
+                if let Dependency::Detailed { features: old, .. } = &mut dep {
+                    *old = features;
                 }
+                dep
             }
         }
     }
+
+    pub fn get_package(&self) -> Option<String> {
+        match self {
+            Dependency::Simple(_) | Dependency::Workspace { .. } => None,
+            Dependency::Detailed { package, .. } => package.clone(),
+        }
+    }
+
+    pub fn get_features(&self) -> Vec<String> {
+        match self {
+            Dependency::Simple(_) => Vec::new(),
+            Dependency::Detailed { features, .. } | Dependency::Workspace { features, .. } => {
+                features.clone().unwrap_or_default()
+            }
+        }
+    }
+
+    pub fn get_url(&self) -> String {
+        match self {
+            Dependency::Simple(url) => url.clone(),
+            Dependency::Detailed { url, .. } => url.clone().unwrap_or_default(),
+            Dependency::Workspace { .. } => String::new(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
@@ -137,12 +506,42 @@ pub struct GithubDependency {
     pub hash: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct ArchiveDependency {
+    pub url: String,
+    pub sha256: Option<String>,
+    pub strip_prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct RockDependency {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WorkspaceConfig {
     pub fn new() -> Self {
         Self {
             package: None,
             dependencies: None,
             dev_dependencies: None,
+            hosts: None,
+            rocks: None,
+            features: None,
+            target: None,
+            patch: None,
+            scripts: None,
+            build: None,
+            test: None,
+            install: None,
+            bin: None,
+            workspace: None,
         }
     }
 
@@ -157,39 +556,16 @@ impl WorkspaceConfig {
     pub fn parse_toml_file(file_path: &str) -> Result<Self, std::io::Error> {
         let toml_str = std::fs::read_to_string(file_path)?;
         Self::parse_toml_str(&toml_str)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            .map_err(std::io::Error::other)
     }
 
     pub fn to_toml_file(&self, file_path: &str) -> Result<(), std::io::Error> {
         let toml_str = self
             .to_toml_str()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
         std::fs::write(file_path, toml_str)
     }
 
-    pub fn try_merge_lock_file(&mut self, lock_file_path: &str) {
-        let lock_file = WorkspaceLock::parse_toml_file(lock_file_path);
-        if let Ok(lock_file) = lock_file {
-            if let Some(dependencies) = lock_file.dependencies {
-                if let Some(self_dependencies) = &mut self.dependencies {
-                    for (name, dep) in self_dependencies {
-                        if let Some(lock_dep) = dependencies.get(name) {
-                            dep.try_merge_lock_dependency(lock_dep);
-                        }
-                    }
-                }
-
-                if let Some(self_dev_dependencies) = &mut self.dev_dependencies {
-                    for (name, dep) in self_dev_dependencies {
-                        if let Some(lock_dep) = dependencies.get(name) {
-                            dep.try_merge_lock_dependency(lock_dep);
-                        }
-                    }
-                }
-            }
-        }
-    }
-
     pub fn add_dependency(&mut self, name: String, dep: Dependency) {
         if let Some(deps) = &mut self.dependencies {
             deps.remove(&name);
@@ -231,6 +607,33 @@ impl WorkspaceConfig {
     pub fn get_dev_dependency(&self, name: &str) -> Option<&Dependency> {
         self.dev_dependencies.as_ref()?.get(name)
     }
+
+    /// `[dependencies]` together with the ones of `target`, which win over a
+    /// dependency of the same name.
+    pub fn target_dependencies(&self, target: Option<&str>) -> HashMap<String, Dependency> {
+        let for_target = self.target_config(target).and_then(|t| t.dependencies.as_ref());
+        merge_dependencies(self.dependencies.as_ref(), for_target)
+    }
+
+    pub fn target_dev_dependencies(&self, target: Option<&str>) -> HashMap<String, Dependency> {
+        let for_target = self
+            .target_config(target)
+            .and_then(|t| t.dev_dependencies.as_ref());
+        merge_dependencies(self.dev_dependencies.as_ref(), for_target)
+    }
+
+    fn target_config(&self, target: Option<&str>) -> Option<&TargetConfig> {
+        self.target.as_ref()?.get(target?)
+    }
+}
+
+fn merge_dependencies(
+    base: Option<&HashMap<String, Dependency>>,
+    for_target: Option<&HashMap<String, Dependency>>,
+) -> HashMap<String, Dependency> {
+    let mut deps = base.cloned().unwrap_or_default();
+    deps.extend(for_target.cloned().unwrap_or_default());
+    deps
 }
 
 #[cfg(test)]
@@ -244,18 +647,129 @@ mod test {
             name: Some("example".to_string()),
             version: Some("0.1.0".to_string()),
             path: None,
+            lua: None,
+            default_target: None,
         };
 
         let config = WorkspaceConfig {
             package: Some(package),
             dependencies: None,
             dev_dependencies: None,
+            hosts: None,
+            rocks: None,
+            features: None,
+            target: None,
+            patch: None,
+            scripts: None,
+            build: None,
+            test: None,
+            install: None,
+            bin: None,
+            workspace: None,
         };
 
         let serialized = config.to_toml_str().unwrap();
         println!("{}", serialized);
     }
 
+    #[test]
+    fn test_local_path_dependency() {
+        let toml_str = r#"
+            [dependencies]
+            mylib = { path = "../shared/mylib" }
+            json = { url = "rxi/json.lua", path = "3rd/json" }
+            "#;
+
+        let config = WorkspaceConfig::parse_toml_str(toml_str).unwrap();
+        let mylib = config.get_dependency("mylib").unwrap();
+        assert_eq!(mylib.get_local_path(), Some("../shared/mylib".to_string()));
+        let json = config.get_dependency("json").unwrap();
+        assert_eq!(json.get_local_path(), None);
+        assert_eq!(json.get_path(), Some("3rd/json".to_string()));
+    }
+
+    #[test]
+    fn test_workspace_dependency() {
+        let toml_str = r#"
+            [workspace.dependencies]
+            json = { url = "rxi/json.lua", tag = "v2.1", features = ["fast"] }
+            say = "lunarmodules/say"
+
+            [dependencies]
+            json = { workspace = true, features = ["pretty"], optional = true }
+            say = { workspace = true }
+            "#;
+
+        let config = WorkspaceConfig::parse_toml_str(toml_str).unwrap();
+        let json = config.get_dependency("json").unwrap();
+        assert!(json.is_workspace());
+        let workspace = config.workspace.as_ref().unwrap();
+        let inherited = workspace.dependencies.as_ref().unwrap()["json"].inherited_by(json);
+        assert_eq!(inherited.get_github_dependency().tag, Some("v2.1".to_string()));
+        assert_eq!(inherited.get_features(), vec!["fast", "pretty"]);
+        assert!(inherited.is_optional());
+        let say = config.get_dependency("say").unwrap();
+        let inherited = workspace.dependencies.as_ref().unwrap()["say"].inherited_by(say);
+        assert_eq!(inherited.get_url(), "lunarmodules/say");
+        assert!(!inherited.is_workspace());
+    }
+
+    #[test]
+    fn test_workspace_dependency_must_be_true() {
+        let toml_str = r#"
+            [dependencies]
+            json = { workspace = false }
+            "#;
+
+        let err = WorkspaceConfig::parse_toml_str(toml_str).unwrap_err();
+        assert!(err.message().contains("`workspace` must be true"), "{}", err);
+    }
+
+    #[test]
+    fn test_workspace_dependency_rejects_source_keys() {
+        let toml_str = r#"
+            [dependencies]
+            json = { workspace = true, url = "rxi/json.lua" }
+            "#;
+
+        let err = WorkspaceConfig::parse_toml_str(toml_str).unwrap_err();
+        assert!(
+            err.message()
+                .contains("`url` is set by the workspace, it cannot be used with `workspace`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_rock_dependency() {
+        let toml_str = r#"
+            [rocks]
+            server = "./rocks"
+
+            [dependencies]
+            lpeg = { rock = "lpeg" }
+            say = { rock = { name = "say", version = "1.4.1" } }
+            "#;
+
+        let config = WorkspaceConfig::parse_toml_str(toml_str).unwrap();
+        assert_eq!(config.rocks.unwrap().server, Some("./rocks".to_string()));
+        let lpeg = config.dependencies.as_ref().unwrap()["lpeg"].clone();
+        assert_eq!(lpeg.get_local_path(), None);
+        assert_eq!(
+            lpeg.get_rock_dependency(),
+            Some(RockDependency {
+                name: "lpeg".to_string(),
+                version: None,
+            })
+        );
+        let say = config.dependencies.as_ref().unwrap()["say"].clone();
+        assert_eq!(
+            say.get_rock_dependency().unwrap().version,
+            Some("1.4.1".to_string())
+        );
+    }
+
     #[test]
     fn test_workspace_config_deserialization() {
         let toml_str = r#"
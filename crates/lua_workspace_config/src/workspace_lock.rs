@@ -1,24 +1,160 @@
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::workspace_config::Dependency;
 
-// but file name is package.toml
-#[derive(Debug, Deserialize, Serialize)]
+pub const LOCK_VERSION: u32 = 2;
+
+// file name is package.lock, one entry for every package of the resolved graph
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct WorkspaceLock {
-    pub dependencies: Option<HashMap<String, Dependency>>,
+    pub version: u32,
+    /// The Lua runtime the graph was resolved for, see `--target`.
+    pub target: Option<String>,
+    /// Features of the root package that were enabled with `--features`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, rename = "package", skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyGroup {
+    #[default]
+    Normal,
+    Dev,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[default]
+    Git,
+    /// A local package used in place, `path` is where it is.
+    Path,
+    /// A release archive, `checksum` is the sha256 of the archive file.
+    Archive,
+    /// A LuaRocks rock, `url` is the `.src.rock` or `.rockspec` it came from.
+    Rock,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, Clone, Default)]
+pub struct LockedPackage {
+    pub name: String,
+    #[serde(default)]
+    pub source: SourceKind,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commit: String,
+    pub tag: Option<String>,
+    pub branch: Option<String>,
+    /// The rock version, such as `1.1.0-1`.
+    pub version: Option<String>,
+    pub path: Option<String>,
+    pub checksum: Option<String>,
+    #[serde(rename = "strip-prefix")]
+    pub strip_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// The url that `[patch]` replaced with this source.
+    pub patched: Option<String>,
+    /// The upstream name of a package installed under another name.
+    pub package: Option<String>,
+    #[serde(default)]
+    pub group: DependencyGroup,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<String>,
+}
+
+// lock files without a version header, the per-directory `[dependencies]` table
+#[derive(Debug, Deserialize)]
+struct WorkspaceLockV1 {
+    dependencies: Option<HashMap<String, Dependency>>,
+}
+
+impl WorkspaceLockV1 {
+    fn migrate(self) -> WorkspaceLock {
+        let mut lock = WorkspaceLock::new();
+        // without a commit there is nothing to keep, the package is resolved again
+        for (name, dep) in self.dependencies.unwrap_or_default() {
+            let github = dep.get_github_dependency();
+            let Some(commit) = github.hash else {
+                continue;
+            };
+            lock.add_package(LockedPackage {
+                name,
+                source: SourceKind::Git,
+                url: github.url,
+                commit,
+                tag: github.tag,
+                branch: github.branch,
+                version: None,
+                path: dep.get_path(),
+                checksum: None,
+                strip_prefix: None,
+                features: Vec::new(),
+                patched: None,
+                package: None,
+                group: DependencyGroup::Normal,
+                dependencies: Vec::new(),
+                dependents: Vec::new(),
+            });
+        }
+        lock
+    }
+}
+
+impl Default for WorkspaceLock {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WorkspaceLock {
     pub fn new() -> Self {
         Self {
-            dependencies: None,
+            version: LOCK_VERSION,
+            target: None,
+            features: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+
+    /// Returns the format version of a lock file, files written before the
+    /// version header existed are version 1.
+    pub fn read_version(toml: &str) -> Result<u32, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: Option<u32>,
         }
+
+        let header: Header = toml::from_str(toml)?;
+        Ok(header.version.unwrap_or(1))
     }
 
+    /// Parses a lock file of any known version, migrating older formats.
     pub fn parse_toml_str(toml: &str) -> Result<Self, toml::de::Error> {
-        toml::from_str(toml)
+        match Self::read_version(toml)? {
+            1 => {
+                let lock: WorkspaceLockV1 = toml::from_str(toml)?;
+                Ok(lock.migrate())
+            }
+            LOCK_VERSION => {
+                let mut lock: WorkspaceLock = toml::from_str(toml)?;
+                lock.sort();
+                Ok(lock)
+            }
+            version => Err(serde::de::Error::custom(format!(
+                "unsupported package.lock version {}, this luap understands up to {}",
+                version, LOCK_VERSION
+            ))),
+        }
     }
 
     pub fn to_toml_str(&self) -> Result<String, toml::ser::Error> {
@@ -27,24 +163,161 @@ impl WorkspaceLock {
 
     pub fn parse_toml_file(file_path: &str) -> Result<Self, std::io::Error> {
         let toml_str = std::fs::read_to_string(file_path)?;
-        Self::parse_toml_str(&toml_str)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        Self::parse_toml_str(&toml_str).map_err(std::io::Error::other)
     }
 
     pub fn write_toml_file(&self, file_path: &str) -> Result<(), std::io::Error> {
-        let toml_str = self
-            .to_toml_str()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let toml_str = self.to_toml_str().map_err(std::io::Error::other)?;
         std::fs::write(file_path, toml_str)
     }
 
-    pub fn add_dependency(&mut self, name: String, dep: Dependency) {
-        if let Some(deps) = &mut self.dependencies {
-            deps.insert(name, dep);
-        } else {
-            let mut deps = HashMap::new();
-            deps.insert(name, dep);
-            self.dependencies = Some(deps);
+    /// Adds or replaces a package, keeping the entries and their lists sorted so
+    /// that the serialized file only changes when the graph does.
+    pub fn add_package(&mut self, mut package: LockedPackage) {
+        package.dependencies.sort();
+        package.dependencies.dedup();
+        package.dependents.sort();
+        package.dependents.dedup();
+
+        match self
+            .packages
+            .binary_search_by(|p| p.name.as_str().cmp(&package.name))
+        {
+            Ok(pos) => self.packages[pos] = package,
+            Err(pos) => self.packages.insert(pos, package),
         }
     }
-}
\ No newline at end of file
+
+    pub fn get_package(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages
+            .binary_search_by(|p| p.name.as_str().cmp(name))
+            .ok()
+            .map(|pos| &self.packages[pos])
+    }
+
+    fn sort(&mut self) {
+        let packages = std::mem::take(&mut self.packages);
+        for package in packages {
+            self.add_package(package);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn locked_package(name: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            source: SourceKind::Git,
+            url: format!("https://github.com/example/{}", name),
+            commit: "dbf4b2dd2eb7c23be2773c89eb059dadd6436f94".to_string(),
+            tag: None,
+            branch: None,
+            version: None,
+            path: None,
+            checksum: None,
+            strip_prefix: None,
+            features: Vec::new(),
+            patched: None,
+            package: None,
+            group: DependencyGroup::Normal,
+            dependencies: Vec::new(),
+            dependents: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_workspace_lock_round_trip() {
+        let mut lock = WorkspaceLock::new();
+        lock.add_package(LockedPackage {
+            tag: Some("v0.1.2".to_string()),
+            checksum: Some("sha256:00ff".to_string()),
+            dependents: vec!["foo".to_string(), "app".to_string()],
+            ..locked_package("json")
+        });
+        lock.add_package(LockedPackage {
+            branch: Some("master".to_string()),
+            group: DependencyGroup::Dev,
+            dependencies: vec!["say".to_string()],
+            dependents: vec!["app".to_string()],
+            ..locked_package("busted")
+        });
+
+        let toml_str = lock.to_toml_str().unwrap();
+        assert!(toml_str.starts_with("version = 2\n"));
+        assert!(toml_str.contains("source = \"git\""));
+        assert!(toml_str.contains("group = \"dev\""));
+        assert!(toml_str.find("name = \"busted\"") < toml_str.find("name = \"json\""));
+
+        let parsed = WorkspaceLock::parse_toml_str(&toml_str).unwrap();
+        assert_eq!(parsed.packages, lock.packages);
+        assert_eq!(
+            parsed.get_package("json").unwrap().dependents,
+            vec!["app".to_string(), "foo".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_workspace_lock_migrates_v1() {
+        let legacy = r#"
+            [dependencies.json]
+            url = "https://github.com/rxi/json.lua"
+            tag = "v0.1.2"
+            hash = "dbf4b2dd2eb7c23be2773c89eb059dadd6436f94"
+
+            [dependencies.unpinned]
+            url = "https://github.com/example/unpinned"
+            "#;
+        assert_eq!(WorkspaceLock::read_version(legacy).unwrap(), 1);
+        let lock = WorkspaceLock::parse_toml_str(legacy).unwrap();
+        assert_eq!(lock.version, LOCK_VERSION);
+        assert_eq!(lock.packages.len(), 1);
+        let json = lock.get_package("json").unwrap();
+        assert_eq!(json.commit, "dbf4b2dd2eb7c23be2773c89eb059dadd6436f94");
+        assert_eq!(json.tag, Some("v0.1.2".to_string()));
+    }
+
+    #[test]
+    fn test_workspace_lock_defaults_missing_fields() {
+        let lock = r#"
+            version = 2
+
+            [[package]]
+            name = "json"
+            url = "https://github.com/rxi/json.lua"
+            commit = "dbf4b2dd2eb7c23be2773c89eb059dadd6436f94"
+            "#;
+        let lock = WorkspaceLock::parse_toml_str(lock).unwrap();
+        let json = lock.get_package("json").unwrap();
+        assert_eq!(json.source, SourceKind::Git);
+        assert_eq!(json.group, DependencyGroup::Normal);
+    }
+
+    #[test]
+    fn test_workspace_lock_path_source() {
+        let lock = r#"
+            version = 2
+
+            [[package]]
+            name = "mylib"
+            source = "path"
+            path = "../shared/mylib"
+            "#;
+        let lock = WorkspaceLock::parse_toml_str(lock).unwrap();
+        let mylib = lock.get_package("mylib").unwrap();
+        assert_eq!(mylib.source, SourceKind::Path);
+        assert_eq!(mylib.path.as_deref(), Some("../shared/mylib"));
+        assert!(mylib.commit.is_empty());
+
+        let toml = lock.to_toml_str().unwrap();
+        assert!(!toml.contains("commit"));
+        assert!(toml.contains("source = \"path\""));
+    }
+
+    #[test]
+    fn test_workspace_lock_rejects_newer_versions() {
+        assert!(WorkspaceLock::parse_toml_str("version = 3\n").is_err());
+    }
+}
@@ -6,6 +6,32 @@ pub enum CommandOpt {
     Install {
         #[structopt(long, help = "Dump the library information")]
         dump_library: bool,
+        #[structopt(
+            long,
+            use_delimiter = true,
+            help = "Features of the package to enable, separated by commas"
+        )]
+        features: Vec<String>,
+        #[structopt(long, help = "Lua runtime to install for: lua5.1, lua5.2, lua5.3, lua5.4 or luajit")]
+        target: Option<String>,
+        #[structopt(short = "g", long, help = "Install a package for the user, with its commands in $LUAP_HOME/bin")]
+        global: bool,
+        #[structopt(requires = "global", help = "Url of the package to install globally")]
+        package: Option<String>,
+        #[structopt(short = "p", long = "package", conflicts_with = "global", help = "Only install this member of the workspace and what it needs")]
+        member: Option<String>,
+    },
+    #[structopt(about = "Remove a globally installed package and its commands")]
+    Uninstall {
+        #[structopt(short = "g", long, help = "Uninstall a global package, required")]
+        global: bool,
+        #[structopt(help = "Name of the package to uninstall")]
+        package: String,
+    },
+    #[structopt(about = "List the packages of package.lock, or the global packages with -g")]
+    List {
+        #[structopt(short = "g", long, help = "List the globally installed packages")]
+        global: bool,
     },
     Check {
         #[structopt(long, help = "Dump the library information")]
@@ -18,6 +44,8 @@ pub enum CommandOpt {
         github: String,
         #[structopt(long, help = "Add the package as a development dependency")]
         dev: bool,
+        #[structopt(long, help = "Declare the package in [workspace.dependencies] and inherit it in the current member")]
+        workspace: bool,
         #[structopt(long, help = "Specific commit hash to use")]
         hash: Option<String>,
         #[structopt(long, help = "Specific tag to use")]
@@ -42,4 +70,47 @@ pub enum CommandOpt {
         branch: Option<String>,
     },
     Init,
+    #[structopt(about = "Build the C modules of the package and its dependencies")]
+    Build,
+    #[structopt(about = "Install the dev-dependencies and run the tests")]
+    Test {
+        #[structopt(help = "Only run the tests matching these names")]
+        filters: Vec<String>,
+        #[structopt(long, help = "Print a JSON summary of the results to stdout")]
+        json: bool,
+        #[structopt(last = true, help = "Arguments passed on to the test runner")]
+        args: Vec<String>,
+    },
+    #[structopt(about = "Print LUA_PATH and LUA_CPATH for the installed packages, as in eval \"$(luap env)\"")]
+    Env {
+        #[structopt(long, possible_values = crate::targets::env::SHELLS, help = "Shell to print the statements for, bash by default")]
+        shell: Option<String>,
+    },
+    #[structopt(about = "Run a script of package.toml, or list them when no name is given")]
+    Run {
+        #[structopt(help = "Name of the script to run")]
+        name: Option<String>,
+        #[structopt(last = true, help = "Arguments passed on to the script")]
+        args: Vec<String>,
+    },
+    #[structopt(
+        about = "Run a command with the search paths of the project and its installed commands on PATH",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    Exec {
+        #[structopt(required = true, help = "Command to run and its arguments")]
+        command: Vec<String>,
+    },
+    #[structopt(about = "Check package.toml for mistakes, exits non-zero when there are any")]
+    VerifyManifest {
+        #[structopt(help = "Manifest to check, package.toml by default")]
+        path: Option<String>,
+    },
+    #[structopt(about = "Print the JSON Schema of package.toml or package.lock")]
+    Schema {
+        #[structopt(long, conflicts_with = "lock", required_unless = "lock", help = "Schema of package.toml")]
+        manifest: bool,
+        #[structopt(long, help = "Schema of package.lock")]
+        lock: bool,
+    },
 }
\ No newline at end of file
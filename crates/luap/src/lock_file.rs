@@ -1,61 +1,129 @@
-use std::error::Error;
 use std::path::Path;
 
-use github_package::dep::get_dep_from_repo;
-use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
-use lua_workspace_config::workspace_lock::WorkspaceLock;
+use git2::Oid;
+use github_package::cache::open_cache;
+use rocks_package::{open_cached_rock, rock_checksum};
+use github_package::checksum::git_tree_checksum;
+use lua_workspace_config::workspace_lock::{
+    DependencyGroup, LockedPackage, SourceKind, WorkspaceLock, LOCK_VERSION,
+};
 
-use crate::targets::find_repo_path;
+use crate::resolver::{PackageSource, ResolvedGraph};
 
-pub(crate) fn gen_lock_file(base_path: &Path) -> Result<(), Box<dyn Error>> {
-    let package_path = base_path.join("package.toml");
-    if !package_path.exists() {
-        return Ok(());
+/// Reads package.lock, migrating older formats in place. Only a missing file
+/// is `None`, a lock that cannot be read must not be silently replaced.
+pub(crate) fn read_lock_file(base_path: &Path) -> Result<Option<WorkspaceLock>, std::io::Error> {
+    let Some((version, lock)) = parse_lock_file(base_path)? else {
+        return Ok(None);
+    };
+    if version < LOCK_VERSION {
+        let lock_file_path = base_path.join("package.lock");
+        lock.write_toml_file(lock_file_path.to_str().unwrap())?;
+        eprintln!(
+            "Migrated package.lock from version {} to version {}",
+            version, LOCK_VERSION
+        );
     }
+    Ok(Some(lock))
+}
 
-    let config = WorkspaceConfig::parse_toml_file(&package_path.to_str().unwrap())?;
-    let mut lock_file = WorkspaceLock::new();
+/// Reads package.lock without writing anything, an older format is only
+/// migrated in memory.
+pub(crate) fn peek_lock_file(base_path: &Path) -> Result<Option<WorkspaceLock>, std::io::Error> {
+    Ok(parse_lock_file(base_path)?.map(|(_, lock)| lock))
+}
 
-    if let Some(deps) = &config.dependencies {
-        for (name, dep) in deps {
-            let version = dep.get_version();
-            let path = dep.get_path();
-            let repo_path = find_repo_path(name, version.clone(), path.clone());
-            let github = dep.get_github_dependency();
-            let github_dep = get_dep_from_repo(repo_path.as_path(), &github.url)?;
-            let new_dep = Dependency::Detailed {
-                version: version.clone(),
-                branch: github_dep.branch.clone(),
-                tag: github_dep.tag.clone(),
-                hash: github_dep.hash.clone(),
-                url: github_dep.url.clone(),
-                path: path.clone(),
-            };
-            lock_file.add_dependency(name.to_string(), new_dep);
-            gen_lock_file(&repo_path)?;
-        }
+/// The format version package.lock was written with, older versions are
+/// migrated by the next command that writes it.
+pub(crate) fn lock_file_version(base_path: &Path) -> Result<Option<u32>, std::io::Error> {
+    Ok(parse_lock_file(base_path)?.map(|(version, _)| version))
+}
+
+fn parse_lock_file(base_path: &Path) -> Result<Option<(u32, WorkspaceLock)>, std::io::Error> {
+    let lock_file_path = base_path.join("package.lock");
+    if !lock_file_path.exists() {
+        return Ok(None);
     }
 
-    if let Some(dev_deps) = &config.dev_dependencies {
-        for (name, dep) in dev_deps {
-            let version = dep.get_version();
-            let path = dep.get_path();
-            let repo_path = find_repo_path(name, version.clone(), path.clone());
-            let github = dep.get_github_dependency();
-            let github_dep = get_dep_from_repo(repo_path.as_path(), &github.url)?;
-            let new_dep = Dependency::Detailed {
-                version: version.clone(),
-                branch: github_dep.branch.clone(),
-                tag: github_dep.tag.clone(),
-                hash: github_dep.hash.clone(),
-                url: github_dep.url.clone(),
-                path: path.clone(),
-            };
-            lock_file.add_dependency(name.to_string(), new_dep);
-        }
+    let toml_str = std::fs::read_to_string(lock_file_path)?;
+    let version = WorkspaceLock::read_version(&toml_str).map_err(parse_error)?;
+    let lock = WorkspaceLock::parse_toml_str(&toml_str).map_err(parse_error)?;
+    Ok(Some((version, lock)))
+}
+
+pub(crate) fn gen_lock_file(base_path: &Path, graph: &ResolvedGraph) -> Result<(), std::io::Error> {
+    let mut lock_file = WorkspaceLock::new();
+    lock_file.target = graph.target.clone();
+    lock_file.features = graph.features.clone();
+    for package in graph.packages.values() {
+        let locked = match &package.source {
+            PackageSource::Git {
+                url,
+                commit,
+                tag,
+                branch,
+            } => LockedPackage {
+                source: SourceKind::Git,
+                url: url.clone(),
+                commit: commit.clone(),
+                tag: tag.clone(),
+                branch: branch.clone(),
+                checksum: git_checksum(url, commit),
+                ..Default::default()
+            },
+            // a local package changes under the lock, there is nothing to pin
+            PackageSource::Path { .. } => LockedPackage {
+                source: SourceKind::Path,
+                ..Default::default()
+            },
+            PackageSource::Archive {
+                url,
+                sha256,
+                strip_prefix,
+            } => LockedPackage {
+                source: SourceKind::Archive,
+                url: url.clone(),
+                checksum: Some(format!("sha256:{}", sha256)),
+                strip_prefix: strip_prefix.clone(),
+                ..Default::default()
+            },
+            PackageSource::Rock { version, url, .. } => LockedPackage {
+                source: SourceKind::Rock,
+                url: url.clone(),
+                version: Some(version.clone()),
+                checksum: open_cached_rock(url)
+                    .and_then(|path| rock_checksum(&path))
+                    .ok(),
+                ..Default::default()
+            },
+        };
+        lock_file.add_package(LockedPackage {
+            name: package.name.clone(),
+            path: package.path.clone(),
+            group: if package.dev {
+                DependencyGroup::Dev
+            } else {
+                DependencyGroup::Normal
+            },
+            dependencies: package.dependencies.clone(),
+            dependents: package.dependents.clone(),
+            features: package.features.clone(),
+            patched: package.patched.clone(),
+            package: package.package.clone(),
+            ..locked
+        });
     }
 
     let lock_file_path = base_path.join("package.lock");
-    lock_file.write_toml_file(&lock_file_path.to_str().unwrap())?;
-    Ok(())
+    lock_file.write_toml_file(lock_file_path.to_str().unwrap())
+}
+
+// toml errors end with a newline of their own
+fn parse_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::other(e.to_string().trim_end().to_string())
+}
+
+fn git_checksum(url: &str, commit: &str) -> Option<String> {
+    let repo = open_cache(url).ok()?;
+    git_tree_checksum(&repo, Oid::from_str(commit).ok()?).ok()
 }
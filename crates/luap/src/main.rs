@@ -2,14 +2,69 @@ use structopt::StructOpt;
 
 mod command_opt;
 mod lock_file;
+mod resolver;
 mod targets;
 
 fn main() {
     let opt = command_opt::CommandOpt::from_args();
 
+    // init always starts a new project where it is, a manifest given by path
+    // is read from where it is, global packages are not in any project, exec
+    // runs its command where it was started, and add, remove and update edit
+    // the member of a workspace they run in
+    let in_project = !matches!(
+        opt,
+        command_opt::CommandOpt::Init
+            | command_opt::CommandOpt::Schema { .. }
+            | command_opt::CommandOpt::VerifyManifest { path: Some(_) }
+            | command_opt::CommandOpt::Install { global: true, .. }
+            | command_opt::CommandOpt::Uninstall { .. }
+            | command_opt::CommandOpt::List { global: true }
+            | command_opt::CommandOpt::Exec { .. }
+            | command_opt::CommandOpt::Add { .. }
+            | command_opt::CommandOpt::Remove { .. }
+            | command_opt::CommandOpt::Update { .. }
+    );
+    if in_project {
+        targets::enter_project_root();
+    }
+
     match opt {
-        command_opt::CommandOpt::Install { dump_library } => {
-            targets::install::install_package(dump_library);
+        command_opt::CommandOpt::Install {
+            global: true,
+            package,
+            target,
+            ..
+        } => {
+            targets::global::install_global(package, target);
+        }
+        command_opt::CommandOpt::Install {
+            dump_library,
+            features,
+            target,
+            member,
+            ..
+        } => {
+            // without --features the ones recorded in package.lock are kept
+            let features = (!features.is_empty()).then_some(features);
+            targets::install::install_package(dump_library, features, target, member);
+        }
+        command_opt::CommandOpt::Uninstall { global, package } => {
+            if !global {
+                eprintln!(
+                    "Only global packages are uninstalled, use `luap uninstall -g {}`, or `luap remove {}` for a dependency",
+                    package, package
+                );
+                std::process::exit(1);
+            }
+            targets::global::uninstall_global(&package);
+        }
+        command_opt::CommandOpt::List { global } => {
+            if global {
+                targets::global::list_global();
+            } else {
+                targets::list::list_packages();
+            }
         }
         command_opt::CommandOpt::Check { dump_library } => {
             targets::check::check_package(dump_library);
@@ -21,11 +76,12 @@ fn main() {
             hash,
             tag,
             branch,
+            workspace,
         } => {
             if dev {
-                targets::add::add_dev_package(&package, &github, branch, tag, hash);
+                targets::add::add_dev_package(&package, &github, branch, tag, hash, workspace);
             } else {
-                targets::add::add_package(&package, &github, branch, tag, hash);
+                targets::add::add_package(&package, &github, branch, tag, hash, workspace);
             }
         }
         command_opt::CommandOpt::Remove { package, dev } => {
@@ -46,5 +102,32 @@ fn main() {
         command_opt::CommandOpt::Init => {
             targets::init::init_package();
         }
+        command_opt::CommandOpt::Build => {
+            targets::build::build_package();
+        }
+        command_opt::CommandOpt::Test {
+            filters,
+            json,
+            args,
+        } => {
+            targets::test::test_package(filters, args, json);
+        }
+        command_opt::CommandOpt::Env { shell } => {
+            targets::env::print_env(shell);
+        }
+        command_opt::CommandOpt::Run { name, args } => {
+            targets::run::run_script(name, args);
+        }
+        command_opt::CommandOpt::Exec { command } => {
+            targets::exec::exec_command(command);
+        }
+        command_opt::CommandOpt::VerifyManifest { path } => {
+            targets::verify_manifest::verify_manifest(path);
+        }
+        command_opt::CommandOpt::Schema { manifest, lock } => {
+            // structopt requires exactly one of them
+            debug_assert!(manifest != lock);
+            targets::schema::print_schema(lock);
+        }
     }
 }
@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use archive_package::{fetch_archive, open_cached_archive};
+use git2::{Oid, Repository};
+use github_package::cache::{
+    fetch_to_cache, list_tags, open_cache, read_file_at_commit, resolve_revision,
+};
+use github_package::{normalize_repo_url, parse_repo_url};
+use github_package::tag::RemoteTag;
+use lua_workspace_config::workspace_config::{ArchiveDependency, GithubDependency};
+use rocks_package::{
+    fetch_rock, open_cached_rock, read_rock, read_server_manifest, RockManifest, Rockspec,
+};
+
+/// Answers the questions the resolver asks about a source repository.
+pub(crate) trait SourceFetcher {
+    fn list_tags(&mut self, url: &str) -> Result<Vec<RemoteTag>, String>;
+
+    fn find_commit(&mut self, github: &GithubDependency) -> Result<String, String>;
+
+    fn read_manifest(&mut self, url: &str, commit: &str) -> Result<Option<String>, String>;
+
+    fn read_archive_manifest(
+        &mut self,
+        archive: &ArchiveDependency,
+    ) -> Result<Option<String>, String>;
+
+    fn rock_manifest(&mut self, server: &str) -> Result<RockManifest, String>;
+
+    fn read_rockspec(&mut self, url: &str) -> Result<Rockspec, String>;
+
+    /// Reads the manifest of a local package from disk.
+    fn read_local_manifest(&mut self, path: &str) -> Result<Option<String>, String> {
+        let dir = Path::new(path);
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", path));
+        }
+        match std::fs::read_to_string(dir.join("package.toml")) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Reads everything from the bare mirrors in the luap cache. A mirror is only
+/// refreshed when the question needs the current state of the remote, or when
+/// a locked commit is missing from it, and at most once per run.
+#[derive(Default)]
+pub(crate) struct CacheFetcher {
+    repos: HashMap<String, Repository>,
+    fetched: HashSet<String>,
+    rock_manifests: HashMap<String, RockManifest>,
+}
+
+impl CacheFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn repo(&mut self, url: &str, refresh: bool) -> Result<&Repository, String> {
+        parse_repo_url(url)?;
+        let key = normalize_repo_url(url);
+        if !self.repos.contains_key(&key) {
+            if let Ok(repo) = open_cache(url) {
+                self.repos.insert(key.clone(), repo);
+            }
+        }
+
+        if (refresh || !self.repos.contains_key(&key)) && !self.fetched.contains(&key) {
+            let repo =
+                fetch_to_cache(url).map_err(|e| format!("failed to fetch {}: {}", url, e))?;
+            self.repos.insert(key.clone(), repo);
+            self.fetched.insert(key.clone());
+        }
+        Ok(&self.repos[&key])
+    }
+}
+
+impl SourceFetcher for CacheFetcher {
+    fn list_tags(&mut self, url: &str) -> Result<Vec<RemoteTag>, String> {
+        let repo = self.repo(url, true)?;
+        list_tags(repo).map_err(|e| e.to_string())
+    }
+
+    fn find_commit(&mut self, github: &GithubDependency) -> Result<String, String> {
+        // only a pinned hash can be answered from a stale mirror
+        if github.hash.is_some() {
+            let repo = self.repo(&github.url, false)?;
+            if let Ok(commit) = resolve_revision(repo, github) {
+                return Ok(commit.to_string());
+            }
+        }
+
+        let repo = self.repo(&github.url, true)?;
+        resolve_revision(repo, github)
+            .map(|commit| commit.to_string())
+            .map_err(|e| format!("failed to find revision of {}: {}", github.url, e))
+    }
+
+    fn read_manifest(&mut self, url: &str, commit: &str) -> Result<Option<String>, String> {
+        let commit = Oid::from_str(commit).map_err(|e| e.to_string())?;
+        let repo = self.repo(url, false)?;
+        if repo.find_commit(commit).is_err() {
+            self.repo(url, true)?;
+        }
+
+        let repo = self.repo(url, false)?;
+        read_file_at_commit(repo, commit, "package.toml").map_err(|e| e.to_string())
+    }
+
+    fn read_archive_manifest(
+        &mut self,
+        archive: &ArchiveDependency,
+    ) -> Result<Option<String>, String> {
+        let path = fetch_archive(archive)?;
+        read_archive_file(&path, archive.strip_prefix.as_deref())
+    }
+
+    fn rock_manifest(&mut self, server: &str) -> Result<RockManifest, String> {
+        if !self.rock_manifests.contains_key(server) {
+            let manifest = read_server_manifest(server)?;
+            self.rock_manifests.insert(server.to_string(), manifest);
+        }
+        Ok(self.rock_manifests[server].clone())
+    }
+
+    fn read_rockspec(&mut self, url: &str) -> Result<Rockspec, String> {
+        let path = fetch_rock(url)?;
+        read_rock(&path, url).map(|contents| contents.rockspec)
+    }
+}
+
+/// Reads manifests from the luap cache and never touches the network. Only
+/// useful for a frozen resolve, which never asks for tags or commits.
+#[derive(Default)]
+pub(crate) struct OfflineFetcher;
+
+impl SourceFetcher for OfflineFetcher {
+    fn list_tags(&mut self, url: &str) -> Result<Vec<RemoteTag>, String> {
+        Err(format!("{} is not locked", url))
+    }
+
+    fn find_commit(&mut self, github: &GithubDependency) -> Result<String, String> {
+        Err(format!("{} is not locked", github.url))
+    }
+
+    fn read_manifest(&mut self, url: &str, commit: &str) -> Result<Option<String>, String> {
+        let commit = Oid::from_str(commit).map_err(|e| e.to_string())?;
+        let repo = open_cache(url)
+            .map_err(|_| format!("{} is not in the luap cache, run `luap install`", url))?;
+        read_file_at_commit(&repo, commit, "package.toml").map_err(|e| e.to_string())
+    }
+
+    fn read_archive_manifest(
+        &mut self,
+        archive: &ArchiveDependency,
+    ) -> Result<Option<String>, String> {
+        let path = open_cached_archive(archive)?;
+        read_archive_file(&path, archive.strip_prefix.as_deref())
+    }
+
+    fn rock_manifest(&mut self, server: &str) -> Result<RockManifest, String> {
+        Err(format!("rocks of {} are not locked", server))
+    }
+
+    fn read_rockspec(&mut self, url: &str) -> Result<Rockspec, String> {
+        let path = open_cached_rock(url)?;
+        read_rock(&path, url).map(|contents| contents.rockspec)
+    }
+}
+
+fn read_archive_file(path: &Path, strip_prefix: Option<&str>) -> Result<Option<String>, String> {
+    let manifest = archive_package::read_file(path, strip_prefix, "package.toml")
+        .map_err(|e| e.to_string())?;
+    Ok(manifest.map(|manifest| String::from_utf8_lossy(&manifest).to_string()))
+}
@@ -0,0 +1,1975 @@
+mod fetcher;
+
+pub(crate) use fetcher::{CacheFetcher, OfflineFetcher, SourceFetcher};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::{Component, Path};
+
+use github_package::{expand_host_alias, normalize_repo_url};
+use github_package::tag::{find_best_tag, parse_tag_version, parse_version_req};
+use rocks_package::manifest::{rock_semver, strip_revision};
+use lua_workspace_config::workspace_config::{
+    target_lua_version, ArchiveDependency, Dependency, GithubDependency, RockRequirement,
+    WorkspaceConfig, TARGETS,
+};
+use rocks_package::rockspec::parse_dependency;
+use rocks_package::{rocks_server, Rockspec};
+use lua_workspace_config::workspace_lock::{LockedPackage, SourceKind, WorkspaceLock};
+
+// selections only ever move towards a requirement set that every dependent agrees
+// on, so a graph that has not settled after this many rounds never will
+const MAX_ROUNDS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum PackageSource {
+    Git {
+        url: String,
+        commit: String,
+        tag: Option<String>,
+        branch: Option<String>,
+    },
+    /// A local package used in place, relative to the project root.
+    Path { path: String },
+    Archive {
+        url: String,
+        sha256: String,
+        strip_prefix: Option<String>,
+    },
+    Rock {
+        name: String,
+        version: String,
+        /// The `.src.rock` or `.rockspec` on the rocks server.
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedPackage {
+    pub name: String,
+    pub source: PackageSource,
+    pub path: Option<String>,
+    pub library: Option<String>,
+    pub dependencies: Vec<String>,
+    pub dependents: Vec<String>,
+    pub dev: bool,
+    /// Enabled features, including the optional dependencies they enable.
+    pub features: Vec<String>,
+    /// The url a `[patch]` of the root replaced with this source.
+    pub patched: Option<String>,
+    /// The upstream name when `name` is a local name for the package.
+    pub package: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ResolvedGraph {
+    pub packages: BTreeMap<String, ResolvedPackage>,
+    /// Enabled features of the root package.
+    pub features: Vec<String>,
+    pub target: Option<String>,
+    /// Packages whose `lua` range leaves out the target, and unused patches.
+    pub warnings: Vec<String>,
+}
+
+impl ResolvedGraph {
+    /// Packages ordered so that every package comes after its dependencies.
+    pub fn install_order(&self) -> Vec<&ResolvedPackage> {
+        fn push<'a>(
+            graph: &'a ResolvedGraph,
+            package: &'a ResolvedPackage,
+            order: &mut Vec<&'a ResolvedPackage>,
+            seen: &mut BTreeSet<&'a str>,
+        ) {
+            if !seen.insert(&package.name) {
+                return;
+            }
+            for dep in &package.dependencies {
+                if let Some(dep) = graph.packages.get(dep) {
+                    push(graph, dep, order, seen);
+                }
+            }
+            order.push(package);
+        }
+
+        let mut order = Vec::new();
+        let mut seen = BTreeSet::new();
+        for package in self.packages.values() {
+            push(self, package, &mut order, &mut seen);
+        }
+        order
+    }
+}
+
+impl ResolvedGraph {
+    /// `name` and every package it needs, directly or not.
+    pub fn dependencies_of(&self, name: &str) -> BTreeSet<&str> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            if let Some(package) = self.packages.get(name) {
+                if found.insert(package.name.as_str()) {
+                    pending.extend(package.dependencies.iter().map(String::as_str));
+                }
+            }
+        }
+        found
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Requirement {
+    pub from: String,
+    pub dependency: Dependency,
+}
+
+impl Requirement {
+    fn describe(&self, name: &str) -> String {
+        let name = match self.dependency.get_package() {
+            Some(package) if package != name => format!("{} (package {})", name, package),
+            _ => name.to_string(),
+        };
+        let name = name.as_str();
+        if let Some(path) = self.dependency.get_local_path() {
+            return format!("{} needs {} from {}", self.from, name, path);
+        }
+        if let Some(archive) = self.dependency.get_archive_dependency() {
+            return format!("{} needs {} from {}", self.from, name, archive.url);
+        }
+        if let Some(rock) = self.dependency.get_rock_dependency() {
+            let spec = rock
+                .version
+                .or(self.dependency.get_version())
+                .unwrap_or_else(|| "*".to_string());
+            return format!("{} needs {}@{} from rock {}", self.from, name, spec, rock.name);
+        }
+        let github = self.dependency.get_github_dependency();
+        let pin = github.tag.or(github.hash).or(github.branch);
+        let spec = match (self.dependency.get_version(), pin) {
+            (Some(version), Some(pin)) => format!("{} ({})", version, pin),
+            (Some(spec), None) | (None, Some(spec)) => spec,
+            (None, None) => "*".to_string(),
+        };
+        format!("{} needs {}@{} from {}", self.from, name, spec, github.url)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ResolveError {
+    Conflict {
+        name: String,
+        requirements: Vec<Requirement>,
+    },
+    Cycle(Vec<String>),
+    Invalid {
+        name: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Conflict { name, requirements } => {
+                let requirements: Vec<String> =
+                    requirements.iter().map(|req| req.describe(name)).collect();
+                write!(
+                    f,
+                    "conflicting requirements for {}: {}",
+                    name,
+                    requirements.join(", ")
+                )
+            }
+            ResolveError::Cycle(cycle) => write!(f, "dependency cycle: {}", cycle.join(" -> ")),
+            ResolveError::Invalid { name, message } => write!(f, "{}: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Everything the requirements on one package agree on.
+#[derive(Debug, Default)]
+struct MergedRequirement {
+    url: String,
+    path: Option<String>,
+    archive: Option<ArchiveDependency>,
+    rock: Option<String>,
+    rock_version: Option<String>,
+    hash: Option<String>,
+    tag: Option<String>,
+    branch: Option<String>,
+    versions: Vec<String>,
+}
+
+fn merge_requirements(
+    name: &str,
+    requirements: &[Requirement],
+) -> Result<MergedRequirement, ResolveError> {
+    let conflict = || ResolveError::Conflict {
+        name: name.to_string(),
+        requirements: requirements.to_vec(),
+    };
+
+    let mut merged = MergedRequirement::default();
+    for (i, requirement) in requirements.iter().enumerate() {
+        let github = requirement.dependency.get_github_dependency();
+        let path = requirement.dependency.get_local_path();
+        let archive = requirement.dependency.get_archive_dependency();
+        let rock = requirement.dependency.get_rock_dependency();
+        if i == 0 {
+            merged.url = github.url.clone();
+            merged.path = path;
+            merged.archive = archive;
+            merged.rock = rock.as_ref().map(|rock| rock.name.clone());
+        } else if merged.path != path
+            || merged.archive != archive
+            || merged.rock != rock.as_ref().map(|rock| rock.name.clone())
+            || normalize_repo_url(&merged.url) != normalize_repo_url(&github.url)
+        {
+            return Err(conflict());
+        }
+
+        if let Some(hash) = github.hash {
+            match &merged.hash {
+                // a short hash and the full one name the same commit
+                Some(merged_hash)
+                    if !merged_hash.starts_with(&hash) && !hash.starts_with(merged_hash) =>
+                {
+                    return Err(conflict());
+                }
+                Some(merged_hash) if merged_hash.len() >= hash.len() => {}
+                _ => merged.hash = Some(hash),
+            }
+        }
+        if let Some(version) = rock.and_then(|rock| rock.version) {
+            match &merged.rock_version {
+                Some(merged_version) if *merged_version != version => return Err(conflict()),
+                _ => merged.rock_version = Some(version),
+            }
+        }
+        if let Some(tag) = github.tag {
+            match &merged.tag {
+                Some(merged_tag) if *merged_tag != tag => return Err(conflict()),
+                _ => merged.tag = Some(tag),
+            }
+        }
+        if let Some(branch) = github.branch {
+            match &merged.branch {
+                Some(merged_branch) if *merged_branch != branch => return Err(conflict()),
+                _ => merged.branch = Some(branch),
+            }
+        }
+        if let Some(version) = requirement.dependency.get_version() {
+            if !merged.versions.contains(&version) {
+                merged.versions.push(version);
+            }
+        }
+    }
+
+    if merged.tag.is_some() && merged.branch.is_some() {
+        return Err(conflict());
+    }
+    Ok(merged)
+}
+
+#[derive(Default)]
+struct Walk {
+    requirements: BTreeMap<String, Vec<Requirement>>,
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+    dev: BTreeMap<String, bool>,
+    /// Features asked for by the dependents of each package.
+    requested: BTreeMap<String, BTreeSet<String>>,
+    /// What those features expand to, by the manifest of each package.
+    enabled: BTreeMap<String, BTreeSet<String>>,
+    /// Packages whose source was replaced, and the url it replaced.
+    patched: BTreeMap<String, String>,
+    stack: Vec<String>,
+}
+
+impl Walk {
+    /// The requirements on `name` together with the ones on every other local
+    /// name for the same package, so that they all pick the same revision.
+    fn requirements_for(&self, name: &str) -> Vec<Requirement> {
+        let identity_of = |name: &str| {
+            self.requirements[name]
+                .first()
+                .map(|req| identity(name, &req.dependency))
+        };
+        let own = identity_of(name);
+        self.requirements
+            .keys()
+            .filter(|other| *other == name || identity_of(other) == own)
+            .flat_map(|other| self.requirements[other].iter().cloned())
+            .collect()
+    }
+}
+
+/// What makes two dependencies the same package whatever local name they are
+/// installed as: the upstream name and where it comes from.
+fn identity(name: &str, dependency: &Dependency) -> String {
+    let package = dependency
+        .get_package()
+        .unwrap_or_else(|| name.to_string());
+    let source = if let Some(path) = dependency.get_local_path() {
+        path
+    } else if let Some(archive) = dependency.get_archive_dependency() {
+        archive.url
+    } else if let Some(rock) = dependency.get_rock_dependency() {
+        format!("rock {}", rock.name)
+    } else {
+        normalize_repo_url(&dependency.get_url())
+    };
+    format!("{} from {}", package, source)
+}
+
+/// Builds the whole dependency graph from manifests read out of the source
+/// cache, before anything is checked out into `lua_modules`.
+pub(crate) struct Resolver<F: SourceFetcher> {
+    fetcher: F,
+    locked: BTreeMap<String, LockedPackage>,
+    constraints: BTreeMap<String, Vec<Requirement>>,
+    selected: BTreeMap<String, PackageSource>,
+    manifests: HashMap<PackageSource, Option<WorkspaceConfig>>,
+    frozen: bool,
+    rocks_server: String,
+    root_features: Vec<String>,
+    requested: BTreeMap<String, BTreeSet<String>>,
+    target: Option<String>,
+    /// `[patch]` of the root, the url it replaces and the source replacing it.
+    patches: Vec<(String, Dependency)>,
+    /// Members of the workspace by name, each one at a path relative to the
+    /// root.
+    members: BTreeMap<String, String>,
+    /// `[workspace.dependencies]` of the root, with its host aliases expanded.
+    workspace_dependencies: HashMap<String, Dependency>,
+}
+
+impl<F: SourceFetcher> Resolver<F> {
+    pub fn new(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            locked: BTreeMap::new(),
+            constraints: BTreeMap::new(),
+            selected: BTreeMap::new(),
+            manifests: HashMap::new(),
+            frozen: false,
+            rocks_server: String::new(),
+            root_features: Vec::new(),
+            requested: BTreeMap::new(),
+            target: None,
+            patches: Vec::new(),
+            members: BTreeMap::new(),
+            workspace_dependencies: HashMap::new(),
+        }
+    }
+
+    /// Keeps the locked revision of every package that still satisfies its
+    /// requirements, except for the packages named in `unlocked`.
+    pub fn with_lock(mut self, lock: WorkspaceLock, unlocked: &[String]) -> Self {
+        self.locked = lock
+            .packages
+            .into_iter()
+            .filter(|package| !unlocked.contains(&package.name))
+            .map(|package| (package.name.clone(), package))
+            .collect();
+        self
+    }
+
+    /// Only accepts locked revisions, a package that is not locked or whose
+    /// locked revision no longer satisfies its requirements is an error.
+    pub fn frozen(mut self) -> Self {
+        self.frozen = true;
+        self
+    }
+
+    /// Enables features of the root package, as `luap install --features` does.
+    pub fn with_features(mut self, features: Vec<String>) -> Self {
+        self.root_features = features;
+        self
+    }
+
+    /// Adds the `[target.<target>]` dependencies of every manifest.
+    pub fn with_target(mut self, target: Option<String>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Installs the members of a workspace as dependencies of the root, a
+    /// dependency on a member anywhere in the graph is the member itself.
+    pub fn with_members(mut self, members: Vec<(String, String)>) -> Self {
+        self.members = members.into_iter().collect();
+        self
+    }
+
+    /// Adds a requirement on `name` that does not come from any manifest, such
+    /// as the revision asked for by `luap update <name> --tag ...`.
+    pub fn with_constraint(mut self, name: &str, from: &str, dependency: Dependency) -> Self {
+        self.constraints
+            .entry(name.to_string())
+            .or_default()
+            .push(Requirement {
+                from: from.to_string(),
+                dependency,
+            });
+        self
+    }
+
+    pub fn resolve(&mut self, config: &WorkspaceConfig) -> Result<ResolvedGraph, ResolveError> {
+        let root_name = config
+            .package
+            .as_ref()
+            .and_then(|package| package.name.clone())
+            .unwrap_or_else(|| "root".to_string());
+        // every rock of the graph comes from the server of the root manifest
+        self.rocks_server = rocks_server(
+            config
+                .rocks
+                .as_ref()
+                .and_then(|rocks| rocks.server.as_deref()),
+        );
+
+        if let Some(target) = &self.target {
+            if target_lua_version(target).is_none() {
+                let targets: Vec<&str> = TARGETS.iter().map(|(name, _)| *name).collect();
+                return Err(ResolveError::Invalid {
+                    name: root_name,
+                    message: format!(
+                        "unknown target `{}`, expected one of {}",
+                        target,
+                        targets.join(", ")
+                    ),
+                });
+            }
+        }
+        let target = self.target.clone();
+        let hosts = config.hosts.clone().unwrap_or_default();
+        self.patches = config
+            .patch
+            .iter()
+            .flatten()
+            .map(|(url, patch)| {
+                let patch = match patch.get_local_path() {
+                    Some(_) => patch.clone(),
+                    None => patch.with_url(expand_host_alias(&patch.get_url(), &hosts)),
+                };
+                (expand_host_alias(url, &hosts), patch)
+            })
+            .collect();
+        self.workspace_dependencies = config
+            .workspace
+            .iter()
+            .flat_map(|workspace| workspace.dependencies.iter().flatten())
+            .map(|(name, dep)| {
+                let dep = match (dep.get_local_path(), dep.get_archive_dependency()) {
+                    (None, None) => dep.with_url(expand_host_alias(&dep.get_url(), &hosts)),
+                    _ => dep.clone(),
+                };
+                (name.clone(), dep)
+            })
+            .collect();
+
+        for _ in 0..MAX_ROUNDS {
+            let mut walk = Walk {
+                stack: vec![root_name.clone()],
+                ..Default::default()
+            };
+            let root = Some(Path::new(""));
+            let features = self.root_features.iter().cloned().collect();
+            let root_features =
+                enable_features(&root_name, config, &features, target.as_deref())?;
+            let mut deps = config.target_dependencies(target.as_deref());
+            for (name, path) in &self.members {
+                deps.entry(name.clone()).or_insert_with(|| Dependency::local(path.clone()));
+            }
+            let deps = enabled_dependencies(&deps, &root_features);
+            self.visit(&mut walk, &root_name, &deps, &hosts, root, false)?;
+            let deps = config.target_dev_dependencies(target.as_deref());
+            let deps = enabled_dependencies(&deps, &root_features);
+            self.visit(&mut walk, &root_name, &deps, &hosts, root, true)?;
+            self.visit_member_dev_dependencies(&mut walk)?;
+
+            // a package picked while only some of its dependents had been seen may
+            // not satisfy the rest, so pick again with the full requirement set
+            let mut changed = false;
+            self.selected
+                .retain(|name, _| walk.requirements.contains_key(name));
+            for name in walk.requirements.keys() {
+                let source = self.select(name, &walk.requirements_for(name))?;
+                if self.selected.get(name) != Some(&source) {
+                    self.selected.insert(name.clone(), source);
+                    changed = true;
+                }
+            }
+
+            // features asked for after a package was walked only take effect on
+            // the next walk
+            walk.requested.retain(|_, features| !features.is_empty());
+            if walk.requested != self.requested {
+                self.requested = std::mem::take(&mut walk.requested);
+                changed = true;
+            }
+
+            if !changed {
+                let unused_patches: Vec<String> = self
+                    .patches
+                    .iter()
+                    .filter(|(url, _)| !walk.patched.values().any(|patched| patched == url))
+                    .map(|(url, _)| format!("patch for {} is not used by any dependency", url))
+                    .collect();
+                let mut graph = self.build_graph(walk);
+                graph.features = root_features.into_iter().collect();
+                if let Some(target) = target {
+                    graph.warnings = self.target_warnings(&root_name, config, &target);
+                    graph.target = Some(target);
+                }
+                graph.warnings.extend(unused_patches);
+                return Ok(graph);
+            }
+        }
+
+        Err(ResolveError::Invalid {
+            name: root_name,
+            message: "dependency resolution did not settle".to_string(),
+        })
+    }
+
+    fn visit(
+        &mut self,
+        walk: &mut Walk,
+        from: &str,
+        deps: &HashMap<String, Dependency>,
+        hosts: &HashMap<String, String>,
+        dir: Option<&Path>,
+        dev: bool,
+    ) -> Result<(), ResolveError> {
+        let mut names: Vec<&String> = deps.keys().collect();
+        names.sort();
+
+        for name in names {
+            // an inherited dependency is written in the root, its paths are
+            // relative to it
+            let (declared, dir) = if deps[name].is_workspace() {
+                (self.inherit(from, name, &deps[name])?, Some(Path::new("")))
+            } else {
+                (deps[name].clone(), dir)
+            };
+            let package = declared.get_package().unwrap_or_else(|| name.clone());
+            // host aliases only mean something in the manifest that defines them,
+            // and local paths are relative to it
+            let dependency = match (declared.get_local_path(), dir) {
+                // a member of the workspace is always the one in the repository,
+                // whatever source the manifest names
+                _ if self.members.contains_key(&package) => {
+                    declared.with_source(&Dependency::local(self.members[&package].clone()))
+                }
+                (Some(path), Some(dir)) => declared.with_path(join_path(dir, &path)),
+                (Some(path), None) => {
+                    return Err(ResolveError::Invalid {
+                        name: name.clone(),
+                        message: format!(
+                            "{} is not a local package, its dependency on {} cannot be used",
+                            from, path
+                        ),
+                    })
+                }
+                (None, _) => match declared.get_archive_dependency() {
+                    // a local archive next to a local package is relative to it
+                    Some(archive) if !archive.url.contains("://") => match dir {
+                        Some(dir) => declared.with_archive(join_path(dir, &archive.url)),
+                        None => declared.clone(),
+                    },
+                    Some(_) => declared.clone(),
+                    None => {
+                        let url = expand_host_alias(&declared.get_url(), hosts);
+                        declared.with_url(url)
+                    }
+                },
+            };
+            // a patch replaces the source wherever it is used, its paths are
+            // relative to the root
+            let dependency = match self.find_patch(&dependency) {
+                Some((url, patch)) => {
+                    walk.patched.insert(name.clone(), url);
+                    dependency.with_source(&patch)
+                }
+                None => dependency,
+            };
+            walk.requested
+                .entry(name.clone())
+                .or_default()
+                .extend(dependency.get_features());
+            walk.requirements
+                .entry(name.clone())
+                .or_default()
+                .push(Requirement {
+                    from: from.to_string(),
+                    dependency,
+                });
+            walk.dependencies
+                .entry(from.to_string())
+                .or_default()
+                .insert(name.clone());
+
+            if let Some(pos) = walk.stack.iter().position(|n| n == name) {
+                let mut cycle = walk.stack[pos..].to_vec();
+                cycle.push(name.clone());
+                return Err(ResolveError::Cycle(cycle));
+            }
+
+            // a package reached through normal dependencies is never downgraded
+            // to a dev one
+            match walk.dev.get(name) {
+                Some(false) => continue,
+                Some(true) if dev => continue,
+                _ => {}
+            }
+            walk.dev.insert(name.clone(), dev);
+
+            let source = match self.selected.get(name) {
+                Some(source) => source.clone(),
+                None => {
+                    let source = self.select(name, &walk.requirements_for(name))?;
+                    self.selected.insert(name.clone(), source.clone());
+                    source
+                }
+            };
+
+            let mut features = walk.requested[name].clone();
+            features.extend(self.requested.get(name).into_iter().flatten().cloned());
+            let target = self.target.clone();
+            let manifest = self.manifest(name, &source)?;
+            let enabled = match manifest {
+                Some(manifest) => enable_features(name, manifest, &features, target.as_deref())?,
+                None if features.is_empty() => BTreeSet::new(),
+                None => {
+                    return Err(ResolveError::Invalid {
+                        name: name.clone(),
+                        message: format!("{} has no package.toml to declare features", name),
+                    })
+                }
+            };
+            let deps = manifest
+                .map(|manifest| manifest.target_dependencies(target.as_deref()))
+                .map(|deps| enabled_dependencies(&deps, &enabled));
+            walk.enabled.insert(name.clone(), enabled);
+            let hosts = manifest
+                .and_then(|manifest| manifest.hosts.clone())
+                .unwrap_or_default();
+            let dir = match &source {
+                PackageSource::Path { path } => Some(Path::new(path.as_str()).to_path_buf()),
+                PackageSource::Git { .. }
+                | PackageSource::Archive { .. }
+                | PackageSource::Rock { .. } => None,
+            };
+            if let Some(deps) = deps {
+                walk.stack.push(name.clone());
+                self.visit(walk, name, &deps, &hosts, dir.as_deref(), dev)?;
+                walk.stack.pop();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The entry of `[workspace.dependencies]` that `{ workspace = true }`
+    /// stands for.
+    fn inherit(
+        &self,
+        from: &str,
+        name: &str,
+        dependency: &Dependency,
+    ) -> Result<Dependency, ResolveError> {
+        match self.workspace_dependencies.get(name) {
+            Some(inherited) if !inherited.is_workspace() => Ok(inherited.inherited_by(dependency)),
+            _ => Err(ResolveError::Invalid {
+                name: from.to_string(),
+                message: format!(
+                    "{} is inherited from the workspace, but [workspace.dependencies] of the root has no {}",
+                    name, name
+                ),
+            }),
+        }
+    }
+
+    /// Members are developed in the workspace, so their dev-dependencies are
+    /// installed too, unlike the ones of any other dependency.
+    fn visit_member_dev_dependencies(&mut self, walk: &mut Walk) -> Result<(), ResolveError> {
+        let target = self.target.clone();
+        for (name, path) in self.members.clone() {
+            let Some(source) = self.selected.get(&name).cloned() else {
+                continue;
+            };
+            let Some(manifest) = self.manifest(&name, &source)? else {
+                continue;
+            };
+            let deps = manifest.target_dev_dependencies(target.as_deref());
+            let hosts = manifest.hosts.clone().unwrap_or_default();
+            let enabled = walk.enabled.get(&name).cloned().unwrap_or_default();
+            let deps = enabled_dependencies(&deps, &enabled);
+            walk.stack.push(name.clone());
+            self.visit(walk, &name, &deps, &hosts, Some(Path::new(&path)), true)?;
+            walk.stack.pop();
+        }
+        Ok(())
+    }
+
+    fn find_patch(&self, dependency: &Dependency) -> Option<(String, Dependency)> {
+        if dependency.get_local_path().is_some()
+            || dependency.get_archive_dependency().is_some()
+            || dependency.get_rock_dependency().is_some()
+        {
+            return None;
+        }
+        let url = normalize_repo_url(&dependency.get_url());
+        self.patches
+            .iter()
+            .find(|(patched, _)| normalize_repo_url(patched) == url)
+            .cloned()
+    }
+
+    fn select(
+        &mut self,
+        name: &str,
+        requirements: &[Requirement],
+    ) -> Result<PackageSource, ResolveError> {
+        let mut requirements = requirements.to_vec();
+        if let Some(constraints) = self.constraints.get(name) {
+            requirements.extend(constraints.iter().cloned());
+        }
+        let requirements = requirements.as_slice();
+
+        let merged = merge_requirements(name, requirements)?;
+        let invalid = |message: String| ResolveError::Invalid {
+            name: name.to_string(),
+            message,
+        };
+
+        // local packages have no revisions to pick from
+        if let Some(path) = merged.path {
+            let locked = self.locked.get(name).is_some_and(|locked| {
+                locked.source == SourceKind::Path && locked.path.as_ref() == Some(&path)
+            });
+            if self.frozen && !locked {
+                return Err(invalid(format!("not in package.lock as a path dependency on {}", path)));
+            }
+            return Ok(PackageSource::Path { path });
+        }
+
+        // an archive is pinned by its hash, there is nothing to resolve
+        if let Some(archive) = merged.archive {
+            let Some(sha256) = archive.sha256 else {
+                return Err(invalid(format!("archive {} has no sha256", archive.url)));
+            };
+            let checksum = format!("sha256:{}", sha256);
+            let locked = self.locked.get(name).is_some_and(|locked| {
+                locked.source == SourceKind::Archive
+                    && locked.url == archive.url
+                    && locked.checksum.as_ref() == Some(&checksum)
+                    && locked.strip_prefix == archive.strip_prefix
+            });
+            if self.frozen && !locked {
+                return Err(invalid(format!(
+                    "not in package.lock as an archive from {}",
+                    archive.url
+                )));
+            }
+            return Ok(PackageSource::Archive {
+                url: archive.url,
+                sha256,
+                strip_prefix: archive.strip_prefix,
+            });
+        }
+
+        if let Some(rock) = merged.rock.clone() {
+            return self.select_rock(name, &rock, &merged);
+        }
+
+        if let Some(source) = self.locked_source(name, &merged) {
+            return Ok(source);
+        }
+        if self.frozen {
+            let requirements: Vec<String> =
+                requirements.iter().map(|req| req.describe(name)).collect();
+            return Err(invalid(match self.locked.get(name) {
+                Some(locked) => format!(
+                    "locked revision {} does not satisfy {}",
+                    locked.tag.as_ref().unwrap_or(&locked.commit),
+                    requirements.join(", ")
+                ),
+                None => format!("not in package.lock, {}", requirements.join(", ")),
+            }));
+        }
+        let conflict = || ResolveError::Conflict {
+            name: name.to_string(),
+            requirements: requirements.to_vec(),
+        };
+
+        let mut tag = merged.tag.clone();
+        let mut commit = None;
+        if !merged.versions.is_empty() {
+            let version_req = parse_version_req(&merged.versions.join(", "))
+                .map_err(|e| invalid(e.message().to_string()))?;
+            if let Some(tag) = &merged.tag {
+                let matches = parse_tag_version(tag)
+                    .map(|version| version_req.matches(&version))
+                    .unwrap_or(false);
+                if !matches {
+                    return Err(conflict());
+                }
+            } else if merged.hash.is_none() {
+                let tags = self.fetcher.list_tags(&merged.url).map_err(invalid)?;
+                match find_best_tag(&tags, &version_req) {
+                    Some(best) => {
+                        tag = Some(best.name.clone());
+                        commit = Some(best.commit.clone());
+                    }
+                    None if requirements.len() > 1 => return Err(conflict()),
+                    None => {
+                        return Err(invalid(format!(
+                            "no tag of {} matches version requirement `{}`",
+                            merged.url,
+                            merged.versions.join(", ")
+                        )))
+                    }
+                }
+            }
+        }
+
+        let commit = match commit {
+            Some(commit) => commit,
+            None => {
+                let github = GithubDependency {
+                    url: merged.url.clone(),
+                    tag: tag.clone(),
+                    branch: merged.branch.clone(),
+                    hash: merged.hash.clone(),
+                };
+                self.fetcher.find_commit(&github).map_err(invalid)?
+            }
+        };
+
+        Ok(PackageSource::Git {
+            url: merged.url,
+            commit,
+            tag,
+            branch: merged.branch,
+        })
+    }
+
+    fn select_rock(
+        &mut self,
+        name: &str,
+        rock: &str,
+        merged: &MergedRequirement,
+    ) -> Result<PackageSource, ResolveError> {
+        let invalid = |message: String| ResolveError::Invalid {
+            name: name.to_string(),
+            message,
+        };
+        let version_req = match merged.versions.is_empty() {
+            true => None,
+            false => Some(
+                parse_version_req(&merged.versions.join(", "))
+                    .map_err(|e| invalid(e.message().to_string()))?,
+            ),
+        };
+        let matches = |version: &str| {
+            let pinned = match &merged.rock_version {
+                Some(pin) => version == pin || strip_revision(version) == pin,
+                None => true,
+            };
+            let in_range = match &version_req {
+                Some(req) => rock_semver(version).is_some_and(|v| req.matches(&v)),
+                None => true,
+            };
+            pinned && in_range
+        };
+
+        // a locked rock is kept while it comes from the same server
+        if let Some(locked) = self.locked.get(name) {
+            if let (SourceKind::Rock, Some(version)) = (locked.source, &locked.version) {
+                let prefix = format!("{}/{}-{}.", self.rocks_server, rock, version);
+                if locked.url.starts_with(&prefix) && matches(version) {
+                    return Ok(PackageSource::Rock {
+                        name: rock.to_string(),
+                        version: version.clone(),
+                        url: locked.url.clone(),
+                    });
+                }
+            }
+        }
+        if self.frozen {
+            return Err(invalid(match self.locked.get(name) {
+                Some(locked) => format!(
+                    "locked rock {} does not satisfy the requirements",
+                    locked.version.as_deref().unwrap_or(&locked.url)
+                ),
+                None => format!("not in package.lock, rock {}", rock),
+            }));
+        }
+
+        let manifest = self
+            .fetcher
+            .rock_manifest(&self.rocks_server)
+            .map_err(invalid)?;
+        let release = manifest
+            .find_release(rock, merged.rock_version.as_deref(), version_req.as_ref())
+            .ok_or_else(|| {
+                let spec = merged
+                    .rock_version
+                    .iter()
+                    .chain(merged.versions.iter())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                invalid(match spec.is_empty() {
+                    true => format!("no rock {} on {}", rock, self.rocks_server),
+                    false => format!(
+                        "no version of rock {} on {} matches `{}`",
+                        rock,
+                        self.rocks_server,
+                        spec.join(", ")
+                    ),
+                })
+            })?;
+        Ok(PackageSource::Rock {
+            name: rock.to_string(),
+            url: format!("{}/{}", self.rocks_server, release.file_name()),
+            version: release.version,
+        })
+    }
+
+    fn locked_source(&self, name: &str, merged: &MergedRequirement) -> Option<PackageSource> {
+        let locked = self.locked.get(name)?;
+        if locked.source != SourceKind::Git
+            || normalize_repo_url(&locked.url) != normalize_repo_url(&merged.url)
+            || locked.branch != merged.branch
+        {
+            return None;
+        }
+        if let Some(hash) = &merged.hash {
+            if !locked.commit.starts_with(hash.as_str()) {
+                return None;
+            }
+        }
+        if merged.tag.is_some() && locked.tag != merged.tag {
+            return None;
+        }
+        if !merged.versions.is_empty() {
+            let version_req = parse_version_req(&merged.versions.join(", ")).ok()?;
+            let version = parse_tag_version(locked.tag.as_ref()?)?;
+            if !version_req.matches(&version) {
+                return None;
+            }
+        }
+
+        Some(PackageSource::Git {
+            url: merged.url.clone(),
+            commit: locked.commit.clone(),
+            tag: locked.tag.clone(),
+            branch: locked.branch.clone(),
+        })
+    }
+
+    fn manifest(
+        &mut self,
+        name: &str,
+        source: &PackageSource,
+    ) -> Result<Option<&WorkspaceConfig>, ResolveError> {
+        if !self.manifests.contains_key(source) {
+            let manifest = match source {
+                PackageSource::Rock { url, .. } => {
+                    let manifest = self
+                        .fetcher
+                        .read_rockspec(url)
+                        .map(|rockspec| Some(rockspec_config(&rockspec)))
+                        .map_err(|message| ResolveError::Invalid {
+                            name: name.to_string(),
+                            message,
+                        })?;
+                    self.manifests.insert(source.clone(), manifest);
+                    return Ok(self.manifests[source].as_ref());
+                }
+                PackageSource::Git { url, commit, .. } => self.fetcher.read_manifest(url, commit),
+                PackageSource::Path { path } => self.fetcher.read_local_manifest(path),
+                PackageSource::Archive {
+                    url,
+                    sha256,
+                    strip_prefix,
+                } => self.fetcher.read_archive_manifest(&ArchiveDependency {
+                    url: url.clone(),
+                    sha256: Some(sha256.clone()),
+                    strip_prefix: strip_prefix.clone(),
+                }),
+            };
+            let manifest = manifest
+                .and_then(|manifest| match manifest {
+                    Some(manifest) => WorkspaceConfig::parse_toml_str(&manifest)
+                        .map(Some)
+                        .map_err(|e| format!("failed to parse package.toml: {}", e)),
+                    None => Ok(None),
+                })
+                .map_err(|message| ResolveError::Invalid {
+                    name: name.to_string(),
+                    message,
+                })?;
+            self.manifests.insert(source.clone(), manifest);
+        }
+
+        Ok(self.manifests[source].as_ref())
+    }
+
+    /// Every package of the graph, the root first, whose `lua` range does not
+    /// include the Lua version of `target`.
+    fn target_warnings(&self, root_name: &str, config: &WorkspaceConfig, target: &str) -> Vec<String> {
+        let Some(lua_version) = target_lua_version(target) else {
+            return Vec::new();
+        };
+        let Some(version) = parse_tag_version(lua_version) else {
+            return Vec::new();
+        };
+        let root = std::iter::once((root_name, Some(config)));
+        let packages = self.selected.iter().map(|(name, source)| {
+            let manifest = self.manifests.get(source).and_then(|manifest| manifest.as_ref());
+            (name.as_str(), manifest)
+        });
+
+        let mut warnings = Vec::new();
+        for (name, manifest) in root.chain(packages) {
+            let Some(lua) = manifest
+                .and_then(|manifest| manifest.package.as_ref())
+                .and_then(|package| package.lua.as_ref())
+            else {
+                continue;
+            };
+            match parse_version_req(lua) {
+                Ok(req) if req.matches(&version) => {}
+                Ok(_) => warnings.push(format!(
+                    "{} supports Lua {}, which does not include {} (Lua {})",
+                    name, lua, target, lua_version
+                )),
+                Err(e) => warnings.push(format!("{}: {}", name, e.message())),
+            }
+        }
+        warnings
+    }
+
+    fn build_graph(&self, walk: Walk) -> ResolvedGraph {
+        let mut graph = ResolvedGraph::default();
+        for (name, source) in &self.selected {
+            let requirements = &walk.requirements[name];
+            let dependents: BTreeSet<String> =
+                requirements.iter().map(|req| req.from.clone()).collect();
+            let path = requirements
+                .iter()
+                .find_map(|req| req.dependency.get_path());
+            let library = self
+                .manifests
+                .get(source)
+                .and_then(|manifest| manifest.as_ref())
+                .and_then(|manifest| manifest.package.as_ref())
+                .and_then(|package| package.path.clone());
+
+            graph.packages.insert(
+                name.clone(),
+                ResolvedPackage {
+                    name: name.clone(),
+                    source: source.clone(),
+                    path,
+                    library,
+                    dependencies: walk
+                        .dependencies
+                        .get(name)
+                        .map(|deps| deps.iter().cloned().collect())
+                        .unwrap_or_default(),
+                    dependents: dependents.into_iter().collect(),
+                    dev: walk.dev.get(name).copied().unwrap_or(false),
+                    features: walk
+                        .enabled
+                        .get(name)
+                        .map(|features| features.iter().cloned().collect())
+                        .unwrap_or_default(),
+                    patched: walk.patched.get(name).cloned(),
+                    package: requirements
+                        .iter()
+                        .find_map(|req| req.dependency.get_package())
+                        .filter(|package| package != name),
+                },
+            );
+        }
+        graph
+    }
+}
+
+/// Expands `features` of `name` through its `[features]` table. The result
+/// holds every enabled feature and the optional dependencies they name, a
+/// feature can also be an optional dependency itself.
+fn enable_features(
+    name: &str,
+    manifest: &WorkspaceConfig,
+    features: &BTreeSet<String>,
+    target: Option<&str>,
+) -> Result<BTreeSet<String>, ResolveError> {
+    let table = manifest.features.clone().unwrap_or_default();
+    let deps = manifest.target_dependencies(target);
+    let dev_deps = manifest.target_dev_dependencies(target);
+    let is_dependency =
+        |feature: &str| deps.contains_key(feature) || dev_deps.contains_key(feature);
+
+    let mut enabled = BTreeSet::new();
+    let mut pending: Vec<String> = features.iter().cloned().collect();
+    while let Some(feature) = pending.pop() {
+        if !enabled.insert(feature.clone()) {
+            continue;
+        }
+        match table.get(&feature) {
+            Some(enables) => pending.extend(enables.iter().cloned()),
+            None if is_dependency(&feature) => {}
+            None => {
+                return Err(ResolveError::Invalid {
+                    name: name.to_string(),
+                    message: format!("no feature or optional dependency named `{}`", feature),
+                })
+            }
+        }
+    }
+    Ok(enabled)
+}
+
+fn enabled_dependencies(
+    deps: &HashMap<String, Dependency>,
+    enabled: &BTreeSet<String>,
+) -> HashMap<String, Dependency> {
+    deps.iter()
+        .filter(|(name, dep)| !dep.is_optional() || enabled.contains(*name))
+        .map(|(name, dep)| (name.clone(), dep.clone()))
+        .collect()
+}
+
+/// The dependencies of a rock as if its rockspec were a package.toml, each one
+/// is another rock. `lua` itself is not a dependency luap can install.
+fn rockspec_config(rockspec: &Rockspec) -> WorkspaceConfig {
+    let dependencies = rockspec
+        .dependencies
+        .iter()
+        .filter_map(|dependency| parse_dependency(dependency))
+        .filter(|(name, _)| name != "lua")
+        .map(|(name, version)| {
+            let dependency = Dependency::Detailed {
+                url: None,
+                tag: None,
+                branch: None,
+                hash: None,
+                version,
+                path: None,
+                archive: None,
+                sha256: None,
+                strip_prefix: None,
+                rock: Some(RockRequirement::Name(name.clone())),
+                optional: None,
+                features: None,
+                package: None,
+            };
+            (name, dependency)
+        })
+        .collect();
+
+    let mut config = WorkspaceConfig::new();
+    config.dependencies = Some(dependencies);
+    config
+}
+
+/// Joins a path found in the manifest at `dir` to it, so that every local
+/// package is named relative to the project root.
+fn join_path(dir: &Path, path: &str) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut absolute = String::new();
+    for component in dir.join(path).components() {
+        match component {
+            Component::Prefix(prefix) => {
+                absolute = prefix.as_os_str().to_string_lossy().to_string()
+            }
+            Component::RootDir => absolute.push('/'),
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(last) if last != ".." => {
+                    parts.pop();
+                }
+                _ if !absolute.is_empty() => {}
+                _ => parts.push("..".to_string()),
+            },
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+        }
+    }
+    let path = format!("{}{}", absolute, parts.join("/"));
+    if path.is_empty() {
+        ".".to_string()
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use github_package::tag::RemoteTag;
+    use lua_workspace_config::workspace_lock::LOCK_VERSION;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeRepo {
+        tags: Vec<RemoteTag>,
+        manifests: HashMap<String, String>,
+    }
+
+    #[derive(Default)]
+    struct FakeFetcher {
+        repos: HashMap<String, FakeRepo>,
+        local: HashMap<String, String>,
+    }
+
+    impl FakeFetcher {
+        fn add_tag(&mut self, url: &str, tag: &str, manifest: &str) {
+            let repo = self.repos.entry(url.to_string()).or_default();
+            let commit = format!("{}@{}", url, tag);
+            repo.tags.push(RemoteTag {
+                name: tag.to_string(),
+                commit: commit.clone(),
+            });
+            repo.manifests.insert(commit, manifest.to_string());
+        }
+    }
+
+    impl SourceFetcher for FakeFetcher {
+        fn list_tags(&mut self, url: &str) -> Result<Vec<RemoteTag>, String> {
+            Ok(self
+                .repos
+                .get(url)
+                .map(|r| r.tags.clone())
+                .unwrap_or_default())
+        }
+
+        fn find_commit(&mut self, github: &GithubDependency) -> Result<String, String> {
+            let repo = self.repos.get(&github.url).ok_or("unknown repo")?;
+            match &github.tag {
+                Some(tag) => Ok(format!("{}@{}", github.url, tag)),
+                None => Ok(repo.tags.last().ok_or("empty repo")?.commit.clone()),
+            }
+        }
+
+        fn read_manifest(&mut self, url: &str, commit: &str) -> Result<Option<String>, String> {
+            Ok(self
+                .repos
+                .get(url)
+                .and_then(|r| r.manifests.get(commit).cloned()))
+        }
+
+        fn read_local_manifest(&mut self, path: &str) -> Result<Option<String>, String> {
+            Ok(self.local.get(path).cloned())
+        }
+
+        fn read_archive_manifest(
+            &mut self,
+            archive: &ArchiveDependency,
+        ) -> Result<Option<String>, String> {
+            Ok(self.local.get(&archive.url).cloned())
+        }
+
+        fn rock_manifest(&mut self, server: &str) -> Result<rocks_package::RockManifest, String> {
+            let manifest = self.local.get(&format!("{}/manifest", server));
+            rocks_package::parse_manifest(manifest.ok_or("no manifest")?)
+        }
+
+        fn read_rockspec(&mut self, url: &str) -> Result<Rockspec, String> {
+            rocks_package::parse_rockspec(self.local.get(url).ok_or("no rockspec")?)
+        }
+    }
+
+    fn resolve(fetcher: FakeFetcher, manifest: &str) -> Result<ResolvedGraph, ResolveError> {
+        let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+        Resolver::new(fetcher).resolve(&config)
+    }
+
+    #[test]
+    fn test_resolve_merges_compatible_requirements() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag("json", "v1.2.0", "");
+        fetcher.add_tag("json", "v1.5.0", "");
+        fetcher.add_tag("json", "v2.0.0", "");
+        fetcher.add_tag(
+            "foo",
+            "v1.0.0",
+            r#"
+            [dependencies]
+            json = { url = "json", version = "^1.2" }
+            "#,
+        );
+        fetcher.add_tag(
+            "bar",
+            "v1.0.0",
+            r#"
+            [dependencies]
+            json = { url = "json", version = ">=1.4" }
+            "#,
+        );
+
+        let graph = resolve(
+            fetcher,
+            r#"
+            [package]
+            name = "app"
+
+            [dependencies]
+            foo = "foo"
+            bar = "bar"
+            "#,
+        )
+        .unwrap();
+
+        let json = &graph.packages["json"];
+        assert_eq!(
+            json.source,
+            PackageSource::Git {
+                url: "json".to_string(),
+                commit: "json@v1.5.0".to_string(),
+                tag: Some("v1.5.0".to_string()),
+                branch: None,
+            }
+        );
+        assert_eq!(json.dependents, vec!["bar".to_string(), "foo".to_string()]);
+        assert_eq!(graph.packages["foo"].dependents, vec!["app".to_string()]);
+    }
+
+    #[test]
+    fn test_resolve_reports_conflicts() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag("json-a", "v1.0.0", "");
+        fetcher.add_tag("json-a", "v2.0.0", "");
+        fetcher.add_tag(
+            "foo",
+            "v1.0.0",
+            r#"
+            [dependencies]
+            json = { url = "json-a", version = "^1" }
+            "#,
+        );
+        fetcher.add_tag(
+            "bar",
+            "v1.0.0",
+            r#"
+            [dependencies]
+            json = { url = "json-a", version = "^2" }
+            "#,
+        );
+
+        let err = resolve(
+            fetcher,
+            r#"
+            [dependencies]
+            foo = "foo"
+            bar = "bar"
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "conflicting requirements for json: bar needs json@^2 from json-a, \
+             foo needs json@^1 from json-a"
+        );
+    }
+
+    #[test]
+    fn test_resolve_reports_url_conflicts() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag("json-a", "v1.0.0", "");
+        fetcher.add_tag("json-b", "v1.0.0", "");
+        fetcher.add_tag("foo", "v1.0.0", "[dependencies]\njson = \"json-b\"\n");
+
+        let err = resolve(
+            fetcher,
+            r#"
+            [dependencies]
+            foo = "foo"
+            json = "json-a"
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, ResolveError::Conflict { ref name, .. } if name == "json"));
+    }
+
+    #[test]
+    fn test_resolve_expands_host_aliases_per_manifest() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag(
+            "https://github.example.com/team/json",
+            "v1.0.0",
+            "[hosts]\ncorp = \"git@git.corp.example.com:\"\n\n[dependencies]\nsay = \"corp:lua/say\"\n",
+        );
+        fetcher.add_tag("git@git.corp.example.com:lua/say", "v1.0.0", "");
+
+        let graph = resolve(
+            fetcher,
+            r#"
+            [hosts]
+            ghe = "https://github.example.com"
+
+            [dependencies]
+            json = "ghe:team/json"
+            "#,
+        )
+        .unwrap();
+
+        let url = |name: &str| {
+            let PackageSource::Git { url, .. } = &graph.packages[name].source else {
+                panic!("{} is not a git package", name);
+            };
+            url.clone()
+        };
+        assert_eq!(url("json"), "https://github.example.com/team/json");
+        assert_eq!(url("say"), "git@git.corp.example.com:lua/say");
+    }
+
+    #[test]
+    fn test_resolve_uses_path_dependencies_in_place() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            "../shared/mylib".to_string(),
+            "[dependencies]\nutil = { path = \"../util\" }\njson = \"json\"\n".to_string(),
+        );
+        fetcher.add_tag("json", "v1.0.0", "[dependencies]\nsay = { path = \"../say\" }\n");
+
+        let manifest = "[dependencies]\nmylib = { path = \"../shared/mylib\" }\n";
+        let err = resolve(fetcher, manifest).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "say: json is not a local package, its dependency on ../say cannot be used"
+        );
+
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            "../shared/mylib".to_string(),
+            "[dependencies]\nutil = { path = \"../util\" }\n".to_string(),
+        );
+        let graph = resolve(fetcher, manifest).unwrap();
+        assert_eq!(
+            graph.packages["mylib"].source,
+            PackageSource::Path {
+                path: "../shared/mylib".to_string()
+            }
+        );
+        assert_eq!(
+            graph.packages["util"].path.as_deref(),
+            Some("../shared/util")
+        );
+        assert_eq!(graph.packages["util"].dependents, vec!["mylib".to_string()]);
+    }
+
+    #[test]
+    fn test_resolve_workspace_members() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            "packages/app".to_string(),
+            "[dependencies]\ncore = \"org/core\"\n[dev-dependencies]\nbusted = \"busted\"\n"
+                .to_string(),
+        );
+        fetcher.local.insert(
+            "packages/core".to_string(),
+            "[dependencies]\njson = { workspace = true }\n".to_string(),
+        );
+        fetcher.add_tag("json", "v1.0.0", "");
+        fetcher.add_tag("json", "v2.0.0", "");
+        fetcher.add_tag("busted", "v2.0.0", "[dependencies]\ncore = \"org/core\"\n");
+
+        let manifest = r#"
+            [workspace]
+            members = ["packages/*"]
+
+            [workspace.dependencies]
+            json = { url = "json", tag = "v1.0.0" }
+            "#;
+        let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+        let members = vec![
+            ("app".to_string(), "packages/app".to_string()),
+            ("core".to_string(), "packages/core".to_string()),
+        ];
+        let graph = Resolver::new(fetcher)
+            .with_members(members.clone())
+            .resolve(&config)
+            .unwrap();
+        assert_eq!(
+            graph.packages["json"].source,
+            PackageSource::Git {
+                url: "json".to_string(),
+                commit: "json@v1.0.0".to_string(),
+                tag: Some("v1.0.0".to_string()),
+                branch: None,
+            }
+        );
+        assert_eq!(
+            graph.packages["core"].source,
+            PackageSource::Path {
+                path: "packages/core".to_string()
+            }
+        );
+        assert_eq!(
+            graph.packages["core"].dependents,
+            vec!["app".to_string(), "busted".to_string(), "root".to_string()]
+        );
+        assert!(!graph.packages["core"].dev);
+        assert!(!graph.packages["json"].dev);
+        assert!(graph.packages["busted"].dev);
+        assert_eq!(
+            graph.packages["app"].dependencies,
+            vec!["busted".to_string(), "core".to_string()]
+        );
+
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            "packages/core".to_string(),
+            "[dependencies]\nsay = { workspace = true }\n".to_string(),
+        );
+        let err = Resolver::new(fetcher)
+            .with_members(members)
+            .resolve(&config)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "core: say is inherited from the workspace, but [workspace.dependencies] of the root has no say"
+        );
+    }
+
+    #[test]
+    fn test_resolve_archive_dependencies() {
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            "https://example.com/json-1.0.0.tar.gz".to_string(),
+            format!(
+                "[dependencies]\nsay = {{ archive = \"https://example.com/say.zip\", sha256 = \"{}\" }}\n",
+                sha256
+            ),
+        );
+        let manifest = format!(
+            "[dependencies]\njson = {{ archive = \"https://example.com/json-1.0.0.tar.gz\", sha256 = \"{}\", strip-prefix = \"json-1.0.0\" }}\n",
+            sha256
+        );
+        let graph = resolve(fetcher, &manifest).unwrap();
+        assert_eq!(
+            graph.packages["json"].source,
+            PackageSource::Archive {
+                url: "https://example.com/json-1.0.0.tar.gz".to_string(),
+                sha256: sha256.to_string(),
+                strip_prefix: Some("json-1.0.0".to_string()),
+            }
+        );
+        assert_eq!(graph.packages["say"].dependents, vec!["json".to_string()]);
+
+        let err = resolve(
+            FakeFetcher::default(),
+            "[dependencies]\njson = { archive = \"./vendor/json.tar.gz\" }\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "json: archive vendor/json.tar.gz has no sha256");
+    }
+
+    #[test]
+    fn test_resolve_rock_dependencies() {
+        let server = "https://rocks.example.com";
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            format!("{}/manifest", server),
+            r#"repository = {
+   say = { ["1.3-1"] = { { arch = "src" } }, ["1.4.1-3"] = { { arch = "rockspec" } } },
+   busted = { ["2.2.0-1"] = { { arch = "src" } } },
+}"#
+            .to_string(),
+        );
+        fetcher.local.insert(
+            format!("{}/busted-2.2.0-1.src.rock", server),
+            "package = \"busted\"\nversion = \"2.2.0-1\"\ndependencies = { \"lua >= 5.1\", \"say >= 1.3\" }\n"
+                .to_string(),
+        );
+        fetcher.local.insert(
+            format!("{}/say-1.4.1-3.rockspec", server),
+            "package = \"say\"\nversion = \"1.4.1-3\"\n".to_string(),
+        );
+        let manifest = format!(
+            "[rocks]\nserver = \"{}/\"\n\n[dependencies]\nbusted = {{ rock = {{ name = \"busted\", version = \"2.2.0\" }} }}\n",
+            server
+        );
+        let graph = resolve(fetcher, &manifest).unwrap();
+        assert_eq!(
+            graph.packages["busted"].source,
+            PackageSource::Rock {
+                name: "busted".to_string(),
+                version: "2.2.0-1".to_string(),
+                url: format!("{}/busted-2.2.0-1.src.rock", server),
+            }
+        );
+        // `lua` is not a rock, `say` is pulled in from the same server
+        assert_eq!(graph.packages.len(), 2);
+        assert_eq!(
+            graph.packages["say"].source,
+            PackageSource::Rock {
+                name: "say".to_string(),
+                version: "1.4.1-3".to_string(),
+                url: format!("{}/say-1.4.1-3.rockspec", server),
+            }
+        );
+
+        let mut fetcher = FakeFetcher::default();
+        fetcher.local.insert(
+            format!("{}/manifest", server),
+            "repository = { say = { [\"1.3-1\"] = { { arch = \"src\" } } } }".to_string(),
+        );
+        let manifest = format!(
+            "[rocks]\nserver = \"{}\"\n\n[dependencies]\nsay = {{ rock = \"say\", version = \"^2\" }}\n",
+            server
+        );
+        let err = resolve(fetcher, &manifest).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "say: no version of rock say on https://rocks.example.com matches `^2`"
+        );
+    }
+
+    #[test]
+    fn test_resolve_enables_features() {
+        let fetcher = || {
+            let mut fetcher = FakeFetcher::default();
+            fetcher.add_tag(
+                "json",
+                "v1.0.0",
+                r#"
+[features]
+fast = ["ffi", "cjson"]
+
+[dependencies]
+ffi = { url = "ffi", optional = true }
+cjson = { url = "cjson", optional = true }
+"#,
+            );
+            fetcher.add_tag("ffi", "v1.0.0", "");
+            fetcher.add_tag("cjson", "v1.0.0", "");
+            fetcher
+        };
+        let resolve_features = |manifest: &str, features: &[&str]| {
+            let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+            Resolver::new(fetcher())
+                .with_features(features.iter().map(|f| f.to_string()).collect())
+                .resolve(&config)
+        };
+
+        let graph = resolve_features("[dependencies]\njson = \"json\"\n", &[]).unwrap();
+        assert_eq!(graph.packages.keys().collect::<Vec<_>>(), vec!["json"]);
+
+        // a feature of the dependency, and an optional dependency used as one
+        let manifest = "[dependencies]\njson = { url = \"json\", features = [\"fast\"] }\n";
+        let graph = resolve_features(manifest, &[]).unwrap();
+        assert_eq!(graph.packages.len(), 3);
+        assert_eq!(graph.packages["json"].features, vec!["cjson", "fast", "ffi"]);
+
+        let manifest = r#"
+[features]
+json = ["json-ffi"]
+
+[dependencies]
+json = { url = "json", optional = true }
+json-ffi = { url = "ffi", optional = true }
+"#;
+        let graph = resolve_features(manifest, &["json"]).unwrap();
+        assert_eq!(graph.features, vec!["json", "json-ffi"]);
+        assert_eq!(graph.packages.len(), 2);
+
+        let err = resolve_features(manifest, &["yaml"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "root: no feature or optional dependency named `yaml`"
+        );
+    }
+
+    #[test]
+    fn test_resolve_target_dependencies() {
+        let fetcher = || {
+            let mut fetcher = FakeFetcher::default();
+            fetcher.add_tag("json", "v1.0.0", "[package]\nlua = \">=5.3\"\n");
+            fetcher.add_tag("ffi", "v1.0.0", "[package]\nlua = \"~5.1\"\n");
+            fetcher
+        };
+        let manifest = r#"
+[package]
+name = "app"
+lua = ">=5.1, <5.5"
+
+[dependencies]
+json = "json"
+
+[target.luajit.dependencies]
+ffi = "ffi"
+"#;
+        let resolve_target = |target: Option<&str>| {
+            let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+            Resolver::new(fetcher())
+                .with_target(target.map(str::to_string))
+                .resolve(&config)
+        };
+
+        let graph = resolve_target(None).unwrap();
+        assert_eq!(graph.packages.keys().collect::<Vec<_>>(), vec!["json"]);
+        assert!(graph.warnings.is_empty());
+
+        let graph = resolve_target(Some("luajit")).unwrap();
+        assert_eq!(graph.packages.keys().collect::<Vec<_>>(), vec!["ffi", "json"]);
+        assert_eq!(graph.target, Some("luajit".to_string()));
+        assert_eq!(
+            graph.warnings,
+            vec!["json supports Lua >=5.3, which does not include luajit (Lua 5.1)"]
+        );
+
+        let err = resolve_target(Some("lua6")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "app: unknown target `lua6`, expected one of lua5.1, lua5.2, lua5.3, lua5.4, luajit"
+        );
+    }
+
+    #[test]
+    fn test_resolve_applies_patches() {
+        let fetcher = || {
+            let mut fetcher = FakeFetcher::default();
+            fetcher.add_tag(
+                "https://github.com/a/web",
+                "v1.0.0",
+                "[dependencies]\njson = { url = \"https://github.com/rxi/json.lua\", version = \"^1\" }\n",
+            );
+            fetcher.add_tag("https://github.com/rxi/json.lua", "v1.0.0", "");
+            fetcher.add_tag("https://github.com/me/json.lua", "v1.0.1-fix", "");
+            fetcher
+                .local
+                .insert("../json.lua".to_string(), String::new());
+            fetcher
+        };
+        let resolve_patch = |patch: &str| {
+            let manifest = format!(
+                "[dependencies]\nweb = \"https://github.com/a/web\"\n\n[patch.\"rxi/json.lua\"]\n{}\n",
+                patch
+            );
+            resolve(fetcher(), &manifest).unwrap()
+        };
+
+        let graph = resolve_patch("url = \"https://github.com/me/json.lua\"\ntag = \"v1.0.1-fix\"");
+        let json = &graph.packages["json"];
+        assert_eq!(
+            json.source,
+            PackageSource::Git {
+                url: "https://github.com/me/json.lua".to_string(),
+                commit: "https://github.com/me/json.lua@v1.0.1-fix".to_string(),
+                tag: Some("v1.0.1-fix".to_string()),
+                branch: None,
+            }
+        );
+        assert_eq!(json.patched, Some("rxi/json.lua".to_string()));
+        assert!(graph.warnings.is_empty());
+
+        // paths of a patch are relative to the root, not to the package using it
+        let graph = resolve_patch("path = \"../json.lua\"");
+        assert_eq!(
+            graph.packages["json"].source,
+            PackageSource::Path {
+                path: "../json.lua".to_string()
+            }
+        );
+
+        let manifest = "[dependencies]\njson = \"https://github.com/rxi/json.lua\"\n\n[patch.\"https://github.com/a/say\"]\npath = \"../say\"\n";
+        let graph = resolve(fetcher(), manifest).unwrap();
+        assert_eq!(graph.packages["json"].patched, None);
+        assert_eq!(
+            graph.warnings,
+            vec!["patch for https://github.com/a/say is not used by any dependency"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_renamed_dependencies() {
+        let mut fetcher = FakeFetcher::default();
+        for tag in ["v1.0.0", "v1.2.0", "v1.3.0"] {
+            fetcher.add_tag("a/json", tag, "");
+        }
+        fetcher.add_tag("b/json", "v2.0.0", "");
+        fetcher.add_tag("web", "v1.0.0", "[dependencies]\njson = { url = \"a/json\", version = \"~1.2\" }\n");
+        let manifest = r#"
+[dependencies]
+web = "web"
+json_a = { package = "json", url = "a/json", version = "^1" }
+json_b = { package = "json", url = "b/json" }
+"#;
+        let graph = resolve(fetcher, manifest).unwrap();
+        let commit = |name: &str| match &graph.packages[name].source {
+            PackageSource::Git { commit, .. } => commit.clone(),
+            source => panic!("{} is not a git package: {:?}", name, source),
+        };
+
+        // the same upstream under two names agrees on one revision, a fork of
+        // it is a package of its own
+        assert_eq!(commit("json"), "a/json@v1.2.0");
+        assert_eq!(commit("json_a"), "a/json@v1.2.0");
+        assert_eq!(commit("json_b"), "b/json@v2.0.0");
+        assert_eq!(graph.packages["json_a"].package, Some("json".to_string()));
+        assert_eq!(graph.packages["json"].package, None);
+    }
+
+    #[test]
+    fn test_join_path() {
+        assert_eq!(join_path(Path::new(""), "../shared/mylib"), "../shared/mylib");
+        assert_eq!(join_path(Path::new("../shared/mylib"), "../util"), "../shared/util");
+        assert_eq!(join_path(Path::new("libs/a"), "./../../b"), "b");
+        assert_eq!(join_path(Path::new("libs"), ".."), ".");
+        assert_eq!(join_path(Path::new("libs"), "/opt/lua/lib"), "/opt/lua/lib");
+    }
+
+    #[test]
+    fn test_resolve_detects_cycles() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag("a", "v1.0.0", "[dependencies]\nb = \"b\"\n");
+        fetcher.add_tag("b", "v1.0.0", "[dependencies]\na = \"a\"\n");
+
+        let err = resolve(fetcher, "[dependencies]\na = \"a\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "dependency cycle: a -> b -> a");
+    }
+
+    #[test]
+    fn test_resolve_keeps_locked_revisions() {
+        let fetcher = || {
+            let mut fetcher = FakeFetcher::default();
+            fetcher.add_tag("json", "v1.0.0", "");
+            fetcher.add_tag("json", "v1.5.0", "");
+            fetcher
+        };
+        let lock = || WorkspaceLock {
+            version: LOCK_VERSION,
+            target: None,
+            features: Vec::new(),
+            packages: vec![LockedPackage {
+                name: "json".to_string(),
+                source: Default::default(),
+                url: "json".to_string(),
+                commit: "json@v1.0.0".to_string(),
+                tag: Some("v1.0.0".to_string()),
+                branch: None,
+                version: None,
+                path: None,
+                checksum: None,
+                strip_prefix: None,
+                features: Vec::new(),
+                patched: None,
+                package: None,
+                group: Default::default(),
+                dependencies: Vec::new(),
+                dependents: vec!["root".to_string()],
+            }],
+        };
+        let resolve_commit = |manifest: &str, unlocked: &[String]| {
+            let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+            let graph = Resolver::new(fetcher())
+                .with_lock(lock(), unlocked)
+                .resolve(&config)
+                .unwrap();
+            let PackageSource::Git { commit, .. } = &graph.packages["json"].source else {
+                panic!("json is not a git package");
+            };
+            commit.clone()
+        };
+
+        let manifest = "[dependencies]\njson = { url = \"json\", version = \"^1\" }\n";
+        assert_eq!(resolve_commit(manifest, &[]), "json@v1.0.0");
+        assert_eq!(
+            resolve_commit(manifest, &["json".to_string()]),
+            "json@v1.5.0"
+        );
+
+        // the locked tag no longer satisfies the requirement
+        let manifest = "[dependencies]\njson = { url = \"json\", version = \"^1.2\" }\n";
+        assert_eq!(resolve_commit(manifest, &[]), "json@v1.5.0");
+    }
+
+    #[test]
+    fn test_frozen_resolve_rejects_stale_locks() {
+        let lock = WorkspaceLock {
+            version: LOCK_VERSION,
+            target: None,
+            features: Vec::new(),
+            packages: vec![LockedPackage {
+                name: "json".to_string(),
+                source: Default::default(),
+                url: "json".to_string(),
+                commit: "json@v1.0.0".to_string(),
+                tag: Some("v1.0.0".to_string()),
+                branch: None,
+                version: None,
+                path: None,
+                checksum: None,
+                strip_prefix: None,
+                features: Vec::new(),
+                patched: None,
+                package: None,
+                group: Default::default(),
+                dependencies: Vec::new(),
+                dependents: vec!["root".to_string()],
+            }],
+        };
+        let check = |manifest: &str| {
+            let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+            Resolver::new(FakeFetcher::default())
+                .with_lock(lock.clone(), &[])
+                .frozen()
+                .resolve(&config)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        };
+
+        assert!(check("[dependencies]\njson = { url = \"json\", version = \"^1\" }\n").is_ok());
+        assert_eq!(
+            check("[dependencies]\njson = { url = \"json\", tag = \"nonexistent\" }\n"),
+            Err(
+                "json: locked revision v1.0.0 does not satisfy root needs json@nonexistent from json"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("[dependencies]\nsay = \"say\"\n"),
+            Err("say: not in package.lock, root needs say@* from say".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_marks_dev_only_packages() {
+        let mut fetcher = FakeFetcher::default();
+        fetcher.add_tag("busted", "v2.0.0", "[dependencies]\nsay = \"say\"\n");
+        fetcher.add_tag("say", "v1.0.0", "");
+        fetcher.add_tag("json", "v1.0.0", "[dependencies]\nsay = \"say\"\n");
+
+        let graph = resolve(
+            fetcher,
+            r#"
+            [dependencies]
+            json = "json"
+
+            [dev-dependencies]
+            busted = "busted"
+            "#,
+        )
+        .unwrap();
+
+        assert!(graph.packages["busted"].dev);
+        assert!(!graph.packages["json"].dev);
+        assert!(!graph.packages["say"].dev);
+
+        let order: Vec<&str> = graph
+            .install_order()
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect();
+        assert_eq!(order, vec!["say", "busted", "json"]);
+    }
+}
@@ -1,8 +1,12 @@
 use std::path::Path;
 
-use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
+use lua_workspace_config::manifest_edit::{
+    ManifestEditor, DEPENDENCIES, DEV_DEPENDENCIES, WORKSPACE_DEPENDENCIES,
+};
+use lua_workspace_config::workspace_config::Dependency;
 
-use super::{init::init_package, install::install_package};
+use super::verify_manifest::read_manifest;
+use super::{find_package_root, find_project_root, init::init_package, install::install_package};
 
 pub fn add_package(
     package_name: &str,
@@ -10,8 +14,17 @@ pub fn add_package(
     branch: Option<String>,
     tag: Option<String>,
     hash: Option<String>,
+    workspace: bool,
 ) {
-    match inner_add_package(package_name, github_repo, branch, tag, hash) {
+    match inner_add_package(
+        DEPENDENCIES,
+        package_name,
+        github_repo,
+        branch,
+        tag,
+        hash,
+        workspace,
+    ) {
         Ok(_) => {
             eprintln!("Add package success");
         }
@@ -27,8 +40,17 @@ pub fn add_dev_package(
     branch: Option<String>,
     tag: Option<String>,
     hash: Option<String>,
+    workspace: bool,
 ) {
-    match inner_add_package(package_name, github_repo, branch, tag, hash) {
+    match inner_add_package(
+        DEV_DEPENDENCIES,
+        package_name,
+        github_repo,
+        branch,
+        tag,
+        hash,
+        workspace,
+    ) {
         Ok(_) => {
             eprintln!("Add dev dependency success");
         }
@@ -39,30 +61,84 @@ pub fn add_dev_package(
 }
 
 fn inner_add_package(
+    table: &[&str],
     package_name: &str,
     github_repo: &str,
     branch: Option<String>,
     tag: Option<String>,
     hash: Option<String>,
+    workspace: bool,
 ) -> Result<(), std::io::Error> {
-    let package_toml_path = Path::new("package.toml");
-    if !package_toml_path.exists() {
+    let current_dir = std::env::current_dir()?;
+    let Some(package_root) = find_package_root(&current_dir) else {
         init_package();
         return Ok(());
-    }
+    };
+    // a member of a workspace is installed with the rest of it
+    let project_root = find_project_root(&package_root).unwrap_or(package_root.clone());
 
-    let mut config = WorkspaceConfig::parse_toml_file(package_toml_path.to_str().unwrap())?;
-    let dep = Dependency::Detailed {
-        version: None,
-        url: github_repo.to_string(),
-        branch,
-        tag,
-        hash,
-        path: None,
+    let dep = if branch.is_none() && tag.is_none() && hash.is_none() {
+        Dependency::Simple(github_repo.to_string())
+    } else {
+        Dependency::Detailed {
+            version: None,
+            url: Some(github_repo.to_string()),
+            branch,
+            tag,
+            hash,
+            path: None,
+            archive: None,
+            sha256: None,
+            strip_prefix: None,
+            rock: None,
+            optional: None,
+            features: None,
+            package: None,
+        }
     };
 
-    config.add_dependency(package_name.to_owned(), dep);
-    config.to_toml_file(package_toml_path.to_str().unwrap())?;
-    install_package(false);
+    if workspace {
+        let root_manifest = project_root.join("package.toml");
+        let config = read_manifest(&root_manifest).map_err(std::io::Error::other)?;
+        if config.workspace.is_none() {
+            return Err(std::io::Error::other(format!(
+                "{} has no [workspace] to add {} to",
+                root_manifest.display(),
+                package_name
+            )));
+        }
+        set_dependency(&root_manifest, WORKSPACE_DEPENDENCIES, package_name, &dep)?;
+        if package_root == project_root {
+            eprintln!(
+                "Members use it with `{} = {{ workspace = true }}`",
+                package_name
+            );
+        } else {
+            let inherit = Dependency::Workspace {
+                workspace: true,
+                optional: None,
+                features: None,
+            };
+            let manifest = package_root.join("package.toml");
+            set_dependency(&manifest, table, package_name, &inherit)?;
+        }
+    } else {
+        let manifest = package_root.join("package.toml");
+        set_dependency(&manifest, table, package_name, &dep)?;
+    }
+
+    std::env::set_current_dir(&project_root)?;
+    install_package(false, None, None, None);
     Ok(())
 }
+
+fn set_dependency(
+    path: &Path,
+    table: &[&str],
+    package_name: &str,
+    dep: &Dependency,
+) -> Result<(), std::io::Error> {
+    let mut editor = ManifestEditor::parse_toml_file(path.to_str().unwrap())?;
+    editor.set_dependency(table, package_name, dep);
+    editor.write_toml_file(path.to_str().unwrap())
+}
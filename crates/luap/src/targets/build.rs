@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use lua_workspace_config::workspace_lock::SourceKind;
+use native_build::{build_modules, cache_build_path, find_lua_incdir};
+
+use crate::lock_file::read_lock_file;
+
+use super::layout::InstallDir;
+use super::read_build_config;
+use super::verify_manifest::read_manifest;
+
+/// A package whose package.toml may have C modules to build.
+pub(crate) struct NativePackage {
+    pub name: String,
+    pub path: PathBuf,
+    /// The revision the package was checked out at, builds of a package
+    /// without one are not cached.
+    pub revision: Option<String>,
+}
+
+pub fn build_package() {
+    let base_path = std::env::current_dir().unwrap();
+    if !try_build_package(&base_path) {
+        eprintln!("Build package failed");
+        std::process::exit(1);
+    }
+    eprintln!("Build package success");
+}
+
+/// Builds the C modules of the package and of everything in its lock, for
+/// the target the lock was installed for.
+fn try_build_package(base_path: &Path) -> bool {
+    let package_path = base_path.join("package.toml");
+    if !package_path.exists() {
+        return true;
+    }
+    let config = match read_manifest(&package_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to parse package.toml:\n{}", e);
+            return false;
+        }
+    };
+    let lock = match read_lock_file(base_path) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("Failed to read package.lock: {}", e);
+            return false;
+        }
+    };
+
+    let mut packages = vec![root_package(
+        base_path,
+        config.package.as_ref().and_then(|p| p.name.clone()),
+    )];
+    let install_dir = InstallDir::new(&config);
+    let target = lock.as_ref().and_then(|lock| lock.target.clone());
+    for package in lock.iter().flat_map(|lock| &lock.packages) {
+        let revision = match package.source {
+            SourceKind::Git => Some(package.commit.clone()),
+            SourceKind::Archive | SourceKind::Rock => package.checksum.clone(),
+            SourceKind::Path => None,
+        };
+        packages.push(NativePackage {
+            name: package.name.clone(),
+            path: base_path.join(install_dir.repo_path(&package.name, package.path.clone())),
+            revision,
+        });
+    }
+    let native_path = base_path.join(install_dir.native_path(target.as_deref()));
+    build_native_modules(&native_path, target.as_deref(), &packages)
+}
+
+/// The package itself is always built again, its sources are the ones being
+/// worked on.
+pub(crate) fn root_package(base_path: &Path, name: Option<String>) -> NativePackage {
+    NativePackage {
+        name: name.unwrap_or_else(|| "package".to_string()),
+        path: base_path.to_path_buf(),
+        revision: None,
+    }
+}
+
+/// Builds the C modules of the packages into `out_dir`, which is emptied
+/// first so that nothing of a removed package is left.
+pub(crate) fn build_native_modules(
+    out_dir: &Path,
+    target: Option<&str>,
+    packages: &[NativePackage],
+) -> bool {
+    if out_dir.exists() {
+        if let Err(e) = std::fs::remove_dir_all(out_dir) {
+            eprintln!("Failed to remove {}: {}", out_dir.display(), e);
+            return false;
+        }
+    }
+
+    let builds: Vec<_> = packages
+        .iter()
+        .filter_map(|package| Some((package, read_build_config(&package.path)?)))
+        .filter(|(_, build)| build.modules.as_ref().is_some_and(|m| !m.is_empty()))
+        .collect();
+    if builds.is_empty() {
+        return true;
+    }
+    let lua_incdir = match find_lua_incdir(target) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Failed to build C modules: {}", e);
+            return false;
+        }
+    };
+
+    let mut succ = true;
+    for (package, build) in builds {
+        let cache = package.revision.as_ref().map(|revision| {
+            let revision = revision.trim_start_matches("sha256:");
+            cache_build_path(target.unwrap_or("default"), &package.name, revision)
+        });
+        match build_modules(
+            &package.path,
+            &build,
+            &lua_incdir,
+            cache.as_deref(),
+            out_dir,
+        ) {
+            Ok(output) if output.cached => {}
+            Ok(output) => eprintln!("Built {} of {}", output.modules.join(", "), package.name),
+            Err(e) => {
+                eprintln!("Failed to build C modules of {}: {}", package.name, e);
+                succ = false;
+            }
+        }
+    }
+    succ
+}
@@ -1,9 +1,21 @@
 use std::path::Path;
 
-use github_package::check_github_repo_version;
-use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
+use archive_package::open_cached_archive;
+use github_package::cache::checked_out_commit;
+use github_package::checksum::modified_files;
+use lua_workspace_config::workspace_config::ArchiveDependency;
+use lua_workspace_config::workspace_lock::{
+    LockedPackage, SourceKind, WorkspaceLock, LOCK_VERSION,
+};
+use rocks_package::{open_cached_rock, rock_checksum};
 
-use super::{find_library_path, find_repo_path};
+use crate::lock_file::{lock_file_version, peek_lock_file};
+use crate::resolver::{OfflineFetcher, ResolvedGraph, Resolver};
+
+use super::layout::InstallDir;
+use super::verify_manifest::read_manifest;
+use super::workspace::workspace_members;
+use super::{find_library_path, read_library_path};
 
 pub fn check_package(dump_library: bool) {
     let base_path = std::env::current_dir().unwrap();
@@ -36,55 +48,228 @@ fn try_check_package(base_path: &Path, results: &mut Vec<String>) -> Result<bool
         return Ok(true);
     }
 
-    let config = WorkspaceConfig::parse_toml_file(&package_path.to_str().unwrap());
-    if config.is_err() {
-        eprintln!("Failed to parse package.toml: {:?}", config.err().unwrap());
-        return Ok(false);
-    }
-    let mut config = config.unwrap();
-    let lock_file_path = base_path.join("package.lock");
-    if lock_file_path.exists() {
-        config.try_merge_lock_file(&lock_file_path.to_str().unwrap());
-    }
+    let config = match read_manifest(&package_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to parse package.toml:\n{}", e);
+            return Ok(false);
+        }
+    };
 
     if let Some(package) = &config.package {
         let library_path = find_library_path(base_path, package.path.clone());
         results.push(library_path.to_str().unwrap().to_string());
     }
 
+    // check only reads, an older lock is migrated by the next install
+    let lock = match peek_lock_file(base_path)? {
+        Some(lock) => lock,
+        None => {
+            eprintln!("package.lock not found, run `luap install` first");
+            return Ok(false);
+        }
+    };
     let mut result = true;
-    for (name, dep) in config.dependencies.unwrap() {
-        result &= inner_check_package(&name, &dep, results, false)?;
+    if let Some(version) = lock_file_version(base_path)?.filter(|v| *v < LOCK_VERSION) {
+        eprintln!(
+            "Check package failed: package.lock is version {}, run `luap install` to migrate it to version {}",
+            version, LOCK_VERSION
+        );
+        result = false;
+    }
+
+    let members = match workspace_members(base_path, &config) {
+        Ok(members) => members.into_iter().map(|m| (m.name, m.path)).collect(),
+        Err(e) => {
+            eprintln!("Check package failed: {}", e);
+            return Ok(false);
+        }
+    };
+
+    // every requirement of the manifests must still be met by a locked revision
+    let mut resolver = Resolver::new(OfflineFetcher)
+        .with_features(lock.features.clone())
+        .with_target(lock.target.clone())
+        .with_members(members)
+        .with_lock(lock.clone(), &[])
+        .frozen();
+    match resolver.resolve(&config) {
+        Ok(graph) => result &= check_patches(&graph, &lock),
+        Err(e) => {
+            eprintln!("Check package failed: {}", e);
+            result = false;
+        }
     }
 
-    for (name, dep) in config.dev_dependencies.unwrap_or_default() {
-        result &= inner_check_package(&name, &dep, results, true)?;
+    let install_dir = InstallDir::new(&config);
+    for package in &lock.packages {
+        result &= inner_check_package(&install_dir, lock.target.as_deref(), package, results);
     }
 
     Ok(result)
 }
 
 fn inner_check_package(
-    name: &str,
-    dep: &Dependency,
+    install_dir: &InstallDir,
+    target: Option<&str>,
+    package: &LockedPackage,
     results: &mut Vec<String>,
-    dev: bool,
-) -> Result<bool, std::io::Error> {
-    let github = dep.get_github_dependency();
-    let version = dep.get_version();
-    let path = dep.get_path();
-    let repo_path = find_repo_path(name, version.clone(), path.clone());
-    let library_path = find_library_path(&repo_path, path.clone());
-    results.push(library_path.to_str().unwrap().to_string());
+) -> bool {
+    let repo_path = install_dir.repo_path(&package.name, package.path.clone());
+    let library_path = install_dir.library_path(&repo_path, read_library_path(&repo_path), target);
+    let library_path = library_path.to_str().unwrap().to_string();
+    if !results.contains(&library_path) {
+        results.push(library_path);
+    }
+
+    // a local package is whatever is in its directory
+    if package.source == SourceKind::Path {
+        if !repo_path.is_dir() {
+            eprintln!(
+                "Check package failed: {} is not a directory",
+                repo_path.to_str().unwrap()
+            );
+            return false;
+        }
+        return true;
+    }
+
+    if package.source == SourceKind::Archive {
+        return check_archive_package(package, &repo_path);
+    }
+    if package.source == SourceKind::Rock {
+        return check_rock_package(package, &repo_path);
+    }
+
+    if checked_out_commit(&repo_path).as_ref() != Some(&package.commit) {
+        eprintln!("Check package failed: {}", package.name);
+        return false;
+    }
 
-    let succ = check_github_repo_version(&github, &repo_path)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    if !succ {
-        eprintln!("Check package failed: {}", name);
+    match modified_files(&repo_path) {
+        Ok(files) if files.is_empty() => {}
+        Ok(files) => {
+            eprintln!(
+                "Check package failed: {} has been modified since it was installed: {}",
+                package.name,
+                files.join(", ")
+            );
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Check package failed: {}: {}", package.name, e);
+            return false;
+        }
     }
+    true
+}
+
+// the locked source already matches the patched one, or resolving would have
+// failed, what is left is a lock that does not know it was patched
+fn check_patches(graph: &ResolvedGraph, lock: &WorkspaceLock) -> bool {
+    let describe = |patched: &Option<String>| match patched {
+        Some(url) => format!("patched from {}", url),
+        None => "not patched".to_string(),
+    };
+
+    let mut result = true;
+    for package in graph.packages.values() {
+        let locked = lock
+            .get_package(&package.name)
+            .and_then(|locked| locked.patched.clone());
+        if locked != package.patched {
+            eprintln!(
+                "Check package failed: {} is {} in package.toml but {} in package.lock",
+                package.name,
+                describe(&package.patched),
+                describe(&locked)
+            );
+            result = false;
+        }
+    }
+    result
+}
+
+fn check_archive_package(package: &LockedPackage, repo_path: &Path) -> bool {
+    let archive = ArchiveDependency {
+        url: package.url.clone(),
+        sha256: package
+            .checksum
+            .as_ref()
+            .and_then(|checksum| checksum.strip_prefix("sha256:"))
+            .map(|sha256| sha256.to_string()),
+        strip_prefix: package.strip_prefix.clone(),
+    };
+    let files = open_cached_archive(&archive).and_then(|path| {
+        archive_package::modified_files(&path, archive.strip_prefix.as_deref(), repo_path)
+            .map_err(|e| e.to_string())
+    });
+    match files {
+        Ok(files) if files.is_empty() => true,
+        Ok(files) => {
+            eprintln!(
+                "Check package failed: {} has been modified since it was installed: {}",
+                package.name,
+                files.join(", ")
+            );
+            false
+        }
+        Err(e) => {
+            eprintln!("Check package failed: {}: {}", package.name, e);
+            false
+        }
+    }
+}
+
+fn check_rock_package(package: &LockedPackage, repo_path: &Path) -> bool {
+    let files = open_cached_rock(&package.url).and_then(|path| {
+        let checksum = rock_checksum(&path)?;
+        if package.checksum.as_ref() != Some(&checksum) {
+            return Err(format!(
+                "{} does not match the checksum in package.lock",
+                package.url
+            ));
+        }
+        rocks_package::modified_files(&path, &package.url, repo_path)
+    });
+    match files {
+        Ok(files) if files.is_empty() => true,
+        Ok(files) => {
+            eprintln!(
+                "Check package failed: {} has been modified since it was installed: {}",
+                package.name,
+                files.join(", ")
+            );
+            false
+        }
+        Err(e) => {
+            eprintln!("Check package failed: {}: {}", package.name, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_check_package_keeps_old_lock() {
+        let dir = std::env::temp_dir().join("luap_check_old_lock");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.toml"), "[package]\nname = \"app\"\n").unwrap();
+        fs::write(dir.join("package.lock"), "[dependencies]\n").unwrap();
 
-    if !dev {
-        try_check_package(&repo_path, results)?;
+        let mut results = Vec::new();
+        assert!(!try_check_package(&dir, &mut results).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.join("package.lock")).unwrap(),
+            "[dependencies]\n"
+        );
     }
-    return Ok(succ);
 }
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use crate::lock_file::peek_lock_file;
+
+use super::lua_environment;
+use super::verify_manifest::read_manifest;
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell", "json"];
+
+pub fn print_env(shell: Option<String>) {
+    let base_path = std::env::current_dir().unwrap();
+    match env_script(&base_path, shell.as_deref().unwrap_or("bash")) {
+        Ok(script) => print!("{}", script),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The environment of the installed packages, as statements for the shell.
+/// Only package.toml and package.lock are read, nothing is fetched or written.
+fn env_script(base_path: &Path, shell: &str) -> Result<String, String> {
+    let package_path = base_path.join("package.toml");
+    if !package_path.exists() {
+        return Err("package.toml not found".to_string());
+    }
+    let config = read_manifest(&package_path)
+        .map_err(|e| format!("Failed to parse package.toml:\n{}", e))?;
+    let lock =
+        peek_lock_file(base_path).map_err(|e| format!("Failed to read package.lock: {}", e))?;
+    if lock.is_none() {
+        eprintln!("Warning: package.lock not found, run `luap install` first");
+    }
+    format_env(&lua_environment(base_path, &config, lock.as_ref()), shell)
+}
+
+pub(crate) fn format_env(vars: &[(String, String)], shell: &str) -> Result<String, String> {
+    if shell == "json" {
+        let vars: serde_json::Map<String, serde_json::Value> = vars
+            .iter()
+            .map(|(var, value)| (var.clone(), value.clone().into()))
+            .collect();
+        return Ok(format!("{}\n", serde_json::Value::Object(vars)));
+    }
+
+    let mut script = String::new();
+    for (var, value) in vars {
+        let line = match shell {
+            "bash" | "zsh" => format!("export {}='{}'", var, value.replace('\'', r"'\''")),
+            "fish" => format!(
+                "set -gx {} '{}'",
+                var,
+                value.replace('\\', r"\\").replace('\'', r"\'")
+            ),
+            "powershell" => format!("$env:{} = '{}'", var, value.replace('\'', "''")),
+            _ => {
+                return Err(format!(
+                    "unknown shell `{}`, expected one of {}",
+                    shell,
+                    SHELLS.join(", ")
+                ))
+            }
+        };
+        script.push_str(&line);
+        script.push('\n');
+    }
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_env() {
+        let vars = vec![(
+            "LUA_PATH".to_string(),
+            "/it's/?.lua;C:\\lua\\?.lua;;".to_string(),
+        )];
+        assert_eq!(
+            format_env(&vars, "bash").unwrap(),
+            "export LUA_PATH='/it'\\''s/?.lua;C:\\lua\\?.lua;;'\n"
+        );
+        assert_eq!(
+            format_env(&vars, "fish").unwrap(),
+            "set -gx LUA_PATH '/it\\'s/?.lua;C:\\\\lua\\\\?.lua;;'\n"
+        );
+        assert_eq!(
+            format_env(&vars, "powershell").unwrap(),
+            "$env:LUA_PATH = '/it''s/?.lua;C:\\lua\\?.lua;;'\n"
+        );
+        assert_eq!(
+            format_env(&vars, "json").unwrap(),
+            "{\"LUA_PATH\":\"/it's/?.lua;C:\\\\lua\\\\?.lua;;\"}\n"
+        );
+        assert!(format_env(&vars, "tcsh").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::lock_file::read_lock_file;
+
+use super::layout::InstallDir;
+use super::verify_manifest::read_manifest;
+use super::{find_project_root, lua_environment, path_with_bin};
+
+pub fn exec_command(command: Vec<String>) {
+    let current_dir = std::env::current_dir().unwrap();
+    let Some(base_path) = find_project_root(&current_dir) else {
+        eprintln!("package.toml not found");
+        std::process::exit(1);
+    };
+    match try_exec_command(&base_path, &command) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the command where luap was started, with the search paths of the
+/// project and the installed commands on `PATH`, and returns its exit code.
+fn try_exec_command(base_path: &Path, command: &[String]) -> Result<i32, String> {
+    let (program, args) = command.split_first().ok_or("No command to run")?;
+    let config = read_manifest(&base_path.join("package.toml"))
+        .map_err(|e| format!("Failed to parse package.toml:\n{}", e))?;
+    let lock =
+        read_lock_file(base_path).map_err(|e| format!("Failed to read package.lock: {}", e))?;
+
+    let (path_var, path) = path_with_bin(base_path, &config);
+    // the program is looked up on the PATH of the parent, so resolve it here
+    let program_path = which(program, &path).ok_or_else(|| {
+        format!(
+            "Command `{}` not found, the commands of the packages are in {}",
+            program,
+            InstallDir::new(&config).bin_path().display()
+        )
+    })?;
+    let status = Command::new(program_path)
+        .args(args)
+        .envs(lua_environment(base_path, &config, lock.as_ref()))
+        .env(path_var, path)
+        .status()
+        .map_err(|e| format!("Failed to run `{}`: {}", program, e))?;
+    // a command killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+/// The program found in the directories of `path`, a program given with a
+/// directory is used as it is.
+fn which(program: &str, path: &str) -> Option<PathBuf> {
+    if program.contains(['/', '\\']) {
+        return Some(program.into());
+    }
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    std::env::split_paths(path).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|extension| dir.join(format!("{}{}", program, extension)))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_exec_command() {
+        let dir = std::env::temp_dir().join("luap_exec_command");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let bin_path = dir.join("lua_modules/.bin");
+        fs::create_dir_all(&bin_path).unwrap();
+        fs::write(dir.join("package.toml"), "").unwrap();
+        let tool = bin_path.join("tool");
+        fs::write(&tool, "#!/bin/sh\nexit $1\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let command = vec!["tool".to_string(), "4".to_string()];
+        assert_eq!(try_exec_command(&dir, &command), Ok(4));
+        let missing = vec!["luap-missing-tool".to_string()];
+        assert!(try_exec_command(&dir, &missing)
+            .unwrap_err()
+            .starts_with("Command `luap-missing-tool` not found"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use github_package::luap_home;
+use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
+
+use crate::lock_file::peek_lock_file;
+
+use super::install::{try_install_package, InstallOptions};
+use super::layout::InstallDir;
+use super::list::describe_version;
+use super::shim::{remove_shims, shim_owners, write_shim, Command};
+use super::verify_manifest::read_manifest;
+use super::{isolated_lua_environment, lua_interpreter};
+
+/// Every global package is a project of its own in `$LUAP_HOME/global/<name>`,
+/// with the package as its only dependency.
+fn global_path() -> PathBuf {
+    luap_home().join("global")
+}
+
+/// Where the shims are written, the user puts it on `PATH`.
+fn bin_path() -> PathBuf {
+    luap_home().join("bin")
+}
+
+pub fn install_global(package: Option<String>, target: Option<String>) {
+    let Some(package) = package else {
+        eprintln!("`luap install -g` needs the url of the package to install");
+        std::process::exit(1);
+    };
+    if let Err(e) = try_install_global(&package, target) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn try_install_global(url: &str, target: Option<String>) -> Result<(), String> {
+    let name = package_name(url)
+        .ok_or_else(|| format!("Cannot tell the name of the package at `{}`", url))?;
+    let project = global_path().join(&name);
+    std::fs::create_dir_all(&project)
+        .map_err(|e| format!("Failed to create {}: {}", project.display(), e))?;
+
+    let mut config = WorkspaceConfig::new();
+    config.dependencies = Some(HashMap::from([(
+        name.clone(),
+        Dependency::Simple(url.to_string()),
+    )]));
+    let manifest = project.join("package.toml");
+    config
+        .to_toml_file(manifest.to_str().unwrap())
+        .map_err(|e| format!("Failed to write {}: {}", manifest.display(), e))?;
+
+    // installs are relative to the project they run in
+    std::env::set_current_dir(&project)
+        .map_err(|e| format!("Failed to enter {}: {}", project.display(), e))?;
+    let options = InstallOptions {
+        target,
+        ..Default::default()
+    };
+    if !try_install_package(&project, &options, &mut Vec::new()) {
+        return Err(format!("Install {} failed", name));
+    }
+    let lock =
+        peek_lock_file(&project).map_err(|e| format!("Failed to read package.lock: {}", e))?;
+
+    let repo_path = project.join(InstallDir::new(&config).repo_path(&name, None));
+    let package_path = repo_path.join("package.toml");
+    let bin = if package_path.exists() {
+        read_manifest(&package_path)
+            .map_err(|e| format!("Failed to parse package.toml of {}:\n{}", name, e))?
+            .bin
+            .unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+
+    // commands an earlier version had and this one dropped go away
+    remove_shims(&bin_path(), &name)?;
+    let env = isolated_lua_environment(&project, &config, lock.as_ref());
+    let lua = lua_interpreter(lock.as_ref().and_then(|lock| lock.target.as_deref()));
+    let bin: BTreeMap<String, String> = bin.into_iter().collect();
+    for (command, script) in &bin {
+        let command = Command {
+            package: name.clone(),
+            name: command.clone(),
+            script: repo_path.join(script),
+        };
+        write_shim(&bin_path(), &command, lua, &env)?;
+    }
+
+    if bin.is_empty() {
+        eprintln!("Warning: {} has no [bin] commands", name);
+    } else {
+        let commands: Vec<&str> = bin.keys().map(String::as_str).collect();
+        eprintln!("Installed {} with {}", name, commands.join(", "));
+        let on_path = std::env::var_os("PATH")
+            .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir == bin_path()));
+        if !on_path {
+            eprintln!("Add {} to PATH to run them", bin_path().display());
+        }
+    }
+    Ok(())
+}
+
+pub fn uninstall_global(name: &str) {
+    let project = global_path().join(name);
+    if !project.is_dir() {
+        eprintln!("{} is not installed globally", name);
+        std::process::exit(1);
+    }
+    let result = remove_shims(&bin_path(), name).and_then(|_| {
+        std::fs::remove_dir_all(&project)
+            .map_err(|e| format!("Failed to remove {}: {}", project.display(), e))
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    eprintln!("Uninstalled {}", name);
+}
+
+pub fn list_global() {
+    let Ok(entries) = std::fs::read_dir(global_path()) else {
+        return;
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    let shims = shim_owners(&bin_path());
+    for name in names {
+        let lock = peek_lock_file(&global_path().join(&name)).ok().flatten();
+        let version = lock
+            .as_ref()
+            .and_then(|lock| lock.get_package(&name))
+            .map(describe_version)
+            .unwrap_or_default();
+        let commands: Vec<&str> = shims
+            .iter()
+            .filter(|(_, owner)| **owner == name)
+            .map(|(command, _)| command.as_str())
+            .collect();
+        if commands.is_empty() {
+            println!("{} {}", name, version);
+        } else {
+            println!("{} {}: {}", name, version, commands.join(", "));
+        }
+    }
+}
+
+/// The name a package is installed under, the last part of its url.
+fn package_name(url: &str) -> Option<String> {
+    let last = url
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\', ':'])
+        .next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(package_name("lunarmodules/busted").unwrap(), "busted");
+        assert_eq!(
+            package_name("https://github.com/lunarmodules/luacheck.git/").unwrap(),
+            "luacheck"
+        );
+        assert_eq!(package_name("gh:stevedonovan/LDoc").unwrap(), "LDoc");
+        assert!(package_name("").is_none());
+    }
+}
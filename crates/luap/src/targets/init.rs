@@ -24,6 +24,8 @@ pub(crate) fn init_package() {
         name: Some(dir_name.clone()),
         version: Some("0.1.0".to_string()),
         path: None,
+        lua: None,
+        default_target: None,
     });
     config.dependencies = Some(Default::default());
     config.dev_dependencies = Some(Default::default());
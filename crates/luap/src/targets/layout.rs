@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lua_workspace_config::workspace_config::{
+    target_lua_version, InstallLayout, WorkspaceConfig, DEFAULT_INSTALL_DIR,
+};
+
+use super::find_library_path;
+use super::loader::LOADER_FILE;
+
+/// Where the packages of a project are installed, as set by its `[install]`.
+/// Paths are relative to the project root, like the `path` of a dependency.
+#[derive(Debug)]
+pub(crate) struct InstallDir {
+    pub dir: PathBuf,
+    pub layout: InstallLayout,
+}
+
+impl InstallDir {
+    pub fn new(config: &WorkspaceConfig) -> Self {
+        let install = config.install.as_ref();
+        InstallDir {
+            dir: PathBuf::from(
+                install
+                    .and_then(|install| install.dir.clone())
+                    .unwrap_or_else(|| DEFAULT_INSTALL_DIR.to_string()),
+            ),
+            layout: install
+                .and_then(|install| install.layout)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Where a package is checked out, a package with a `path` is used where
+    /// it is.
+    pub fn repo_path(&self, name: &str, path: Option<String>) -> PathBuf {
+        if let Some(path) = path {
+            return PathBuf::from(path);
+        }
+        match self.layout {
+            InstallLayout::Nested => self.dir.join(name),
+            // kept apart from the modules merged out of them
+            InstallLayout::Flat | InstallLayout::Rocks => self.dir.join(".packages").join(name),
+        }
+    }
+
+    /// The directory the modules of every package are merged into, there is
+    /// none in the `nested` layout.
+    pub fn module_path(&self, target: Option<&str>) -> Option<PathBuf> {
+        match self.layout {
+            InstallLayout::Nested => None,
+            InstallLayout::Flat => Some(self.dir.clone()),
+            InstallLayout::Rocks => Some(self.dir.join("share/lua").join(lua_version(target))),
+        }
+    }
+
+    /// Built C modules are kept apart for every target, so that switching
+    /// targets never loads a module built against another Lua.
+    pub fn native_path(&self, target: Option<&str>) -> PathBuf {
+        match self.layout {
+            InstallLayout::Nested | InstallLayout::Flat => {
+                self.dir.join(".lib").join(target.unwrap_or("default"))
+            }
+            InstallLayout::Rocks => self.dir.join("lib/lua").join(lua_version(target)),
+        }
+    }
+
+    /// Wrappers for the commands of the installed packages, for `luap run`
+    /// and `luap exec` to put on `PATH`.
+    pub fn bin_path(&self) -> PathBuf {
+        self.dir.join(".bin")
+    }
+
+    /// Where `require` finds the modules of the package checked out at
+    /// `repo_path`.
+    pub fn library_path(
+        &self,
+        repo_path: &Path,
+        library: Option<String>,
+        target: Option<&str>,
+    ) -> PathBuf {
+        self.module_path(target)
+            .unwrap_or_else(|| find_library_path(repo_path, library))
+    }
+
+    /// Copies the `.lua` files of the library paths into the module path,
+    /// after removing what an earlier install put there. Of two packages with
+    /// the same module, the first one is kept.
+    pub fn merge_modules(
+        &self,
+        base_path: &Path,
+        target: Option<&str>,
+        packages: &[(String, PathBuf)],
+    ) -> Result<(), String> {
+        let Some(module_path) = self.module_path(target) else {
+            return Ok(());
+        };
+        let module_path = base_path.join(module_path);
+        clear_modules(&module_path).map_err(|e| e.to_string())?;
+
+        let mut owners: HashMap<PathBuf, &str> = HashMap::new();
+        for (name, library_path) in packages {
+            let mut files = Vec::new();
+            lua_files(library_path, Path::new(""), &mut files).map_err(|e| e.to_string())?;
+            for file in files {
+                if let Some(owner) = owners.get(&file) {
+                    eprintln!(
+                        "Warning: {} of {} is also in {}, the one of {} is used",
+                        file.display(),
+                        name,
+                        owner,
+                        owner
+                    );
+                    continue;
+                }
+                let to_path = module_path.join(&file);
+                if let Some(parent) = to_path.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                std::fs::copy(library_path.join(&file), &to_path)
+                    .map_err(|e| format!("failed to copy {}: {}", to_path.display(), e))?;
+                owners.insert(file, name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A tree without a target is laid out for Lua 5.4.
+fn lua_version(target: Option<&str>) -> &'static str {
+    target.and_then(target_lua_version).unwrap_or("5.4")
+}
+
+/// Removes the merged modules, the checkouts, built C modules and the loader
+/// next to them are left alone.
+fn clear_modules(module_path: &Path) -> Result<(), std::io::Error> {
+    let Ok(entries) = std::fs::read_dir(module_path) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.starts_with('.') || file_name == LOADER_FILE {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// The `.lua` files under `dir`, relative to the library path it is in.
+fn lua_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    let mut entries = std::fs::read_dir(root.join(dir))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name();
+        let name = file_name.to_string_lossy();
+        if name.starts_with('.') || name == DEFAULT_INSTALL_DIR {
+            continue;
+        }
+        let path = dir.join(&file_name);
+        if entry.file_type()?.is_dir() {
+            lua_files(root, &path, files)?;
+        } else if name.ends_with(".lua") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use lua_workspace_config::workspace_config::InstallConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_modules() {
+        let dir = std::env::temp_dir().join("luap_merge_modules");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let json = dir.join("vendor/.packages/json");
+        let mylib = dir.join("mylib/src");
+        fs::create_dir_all(&json).unwrap();
+        fs::create_dir_all(mylib.join("mylib")).unwrap();
+        fs::write(json.join("json.lua"), "json").unwrap();
+        fs::write(json.join("README.md"), "").unwrap();
+        fs::write(mylib.join("mylib/init.lua"), "mylib").unwrap();
+        fs::write(mylib.join("json.lua"), "mylib json").unwrap();
+
+        let mut config = WorkspaceConfig::new();
+        config.install = Some(InstallConfig {
+            dir: Some("vendor".to_string()),
+            layout: Some(InstallLayout::Rocks),
+        });
+        let install_dir = InstallDir::new(&config);
+        assert_eq!(
+            install_dir.repo_path("json", None),
+            Path::new("vendor/.packages/json")
+        );
+        assert_eq!(
+            install_dir.native_path(Some("luajit")),
+            Path::new("vendor/lib/lua/5.1")
+        );
+
+        let module_path = dir.join("vendor/share/lua/5.4");
+        fs::create_dir_all(&module_path).unwrap();
+        fs::write(module_path.join("removed.lua"), "").unwrap();
+        let packages = vec![("json".to_string(), json), ("mylib".to_string(), mylib)];
+        install_dir.merge_modules(&dir, None, &packages).unwrap();
+        assert!(!module_path.join("removed.lua").exists());
+        assert!(!module_path.join("README.md").exists());
+        assert_eq!(
+            fs::read_to_string(module_path.join("json.lua")).unwrap(),
+            "json"
+        );
+        assert_eq!(
+            fs::read_to_string(module_path.join("mylib/init.lua")).unwrap(),
+            "mylib"
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use lua_workspace_config::workspace_lock::{LockedPackage, SourceKind};
+
+use crate::lock_file::peek_lock_file;
+
+pub fn list_packages() {
+    let base_path = std::env::current_dir().unwrap();
+    let lock = match peek_lock_file(&base_path) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            eprintln!("package.lock not found, run `luap install` first");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to read package.lock: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for package in &lock.packages {
+        println!("{} {}", package.name, describe_version(package));
+    }
+}
+
+/// What a locked package was resolved to, the tag or version where it has
+/// one.
+pub(crate) fn describe_version(package: &LockedPackage) -> String {
+    match package.source {
+        SourceKind::Path => package.path.clone().unwrap_or_default(),
+        SourceKind::Rock => package.version.clone().unwrap_or_default(),
+        SourceKind::Archive => package.url.clone(),
+        SourceKind::Git => package
+            .tag
+            .clone()
+            .unwrap_or_else(|| package.commit.chars().take(7).collect()),
+    }
+}
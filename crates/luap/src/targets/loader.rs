@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use lua_workspace_config::workspace_config::DEFAULT_INSTALL_DIR;
+
+pub(crate) const LOADER_FILE: &str = "luap_loader.lua";
+
+/// Writes `luap_loader.lua` into the install directory, which adds a searcher
+/// for the installed packages to `package.searchers`, so that an app only has
+/// to `require("lua_modules.luap_loader")` instead of setting `LUA_PATH`.
+///
+/// The library paths are written relative to the loader, which finds them
+/// from where it is itself and keeps working when the project is moved.
+pub(crate) fn write_loader(
+    install_path: &Path,
+    library_paths: &[PathBuf],
+    native_path: &Path,
+) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(install_path)?;
+    let source = loader_source(install_path, library_paths, native_path);
+    std::fs::write(install_path.join(LOADER_FILE), source)
+}
+
+fn loader_source(install_path: &Path, library_paths: &[PathBuf], native_path: &Path) -> String {
+    let mut roots = Vec::new();
+    // the first part of every module name, to the roots that have it
+    let mut modules: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for path in library_paths {
+        let index = roots.len() + 1;
+        roots.push(lua_path_string(&relative_path(install_path, path)));
+        for name in top_level_modules(path) {
+            let entry = modules.entry(name).or_default();
+            if !entry.contains(&index) {
+                entry.push(index);
+            }
+        }
+    }
+
+    let mut source = String::from(LOADER_HEADER);
+    source.push_str("local roots = {\n");
+    for root in &roots {
+        source.push_str(&format!("  {},\n", lua_string(root)));
+    }
+    source.push_str("}\n\nlocal modules = {\n");
+    for (name, indexes) in &modules {
+        let indexes: Vec<String> = indexes.iter().map(usize::to_string).collect();
+        source.push_str(&format!(
+            "  [{}] = {{ {} }},\n",
+            lua_string(name),
+            indexes.join(", ")
+        ));
+    }
+    source.push_str("}\n\n");
+    source.push_str(&format!(
+        "local native = {}\n",
+        lua_string(&lua_path_string(&relative_path(install_path, native_path)))
+    ));
+    source.push_str(LOADER_SEARCHER);
+    source
+}
+
+/// Module names a library root provides at its top level, `json.lua` and a
+/// `json` directory are both `json`.
+fn top_level_modules(path: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with('.') || file_name == DEFAULT_INSTALL_DIR {
+            continue;
+        }
+        let name = if entry.path().is_dir() {
+            Some(file_name.as_str())
+        } else {
+            [".lua", ".so", ".dll"]
+                .iter()
+                .find_map(|extension| file_name.strip_suffix(extension))
+        };
+        if let Some(name) = name {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    names
+}
+
+/// `to` relative to `from`, both absolute. Paths on another drive stay
+/// absolute.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return to.iter().collect();
+    }
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    path.extend(&to[common..]);
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+    path
+}
+
+/// Lua opens files with `/` as the separator on every platform.
+fn lua_path_string(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn lua_string(value: &str) -> String {
+    format!("{:?}", value)
+}
+
+const LOADER_HEADER: &str = r#"-- Generated by `luap install`, changes are overwritten.
+--
+-- require("lua_modules.luap_loader") adds a searcher to `package.searchers`
+-- that finds the installed packages, without LUA_PATH.
+
+"#;
+
+const LOADER_SEARCHER: &str = r#"
+local source = debug.getinfo(1, "S").source
+local base = source:match("^@(.-)luap_loader%.lua$")
+if base == nil or base == "" then
+  base = "./"
+end
+
+local function join(root, path)
+  if root:sub(1, 1) == "/" or root:match("^%a:") then
+    return root .. "/" .. path
+  end
+  return base .. root .. "/" .. path
+end
+
+local function readable(path)
+  local file = io.open(path, "rb")
+  if file then
+    file:close()
+    return true
+  end
+  return false
+end
+
+local extension = package.config:sub(1, 1) == "\\" and ".dll" or ".so"
+
+local function load_native(name, path)
+  local symbol = "luaopen_" .. name:gsub("^[^-]*%-", ""):gsub("%.", "_")
+  local loader, err = package.loadlib(path, symbol)
+  if not loader then
+    error(("error loading module '%s' from file '%s':\n\t%s"):format(name, path, err), 3)
+  end
+  return loader, path
+end
+
+local function searcher(name)
+  local file = name:gsub("%.", "/")
+  local tried = {}
+  for _, index in ipairs(modules[name:match("^[^.]+")] or {}) do
+    local root = roots[index]
+    for _, path in ipairs({ join(root, file .. ".lua"), join(root, file .. "/init.lua") }) do
+      if readable(path) then
+        local chunk, err = loadfile(path)
+        if not chunk then
+          error(("error loading module '%s' from file '%s':\n\t%s"):format(name, path, err), 2)
+        end
+        return chunk, path
+      end
+      tried[#tried + 1] = "no file '" .. path .. "'"
+    end
+    local path = join(root, file .. extension)
+    if readable(path) then
+      return load_native(name, path)
+    end
+  end
+  local path = join(native, file .. extension)
+  if readable(path) then
+    return load_native(name, path)
+  end
+  tried[#tried + 1] = "no file '" .. path .. "'"
+  -- Lua 5.4 puts the separator in front of what a searcher returns itself
+  local separator = "\n\t"
+  if _VERSION == "Lua 5.4" then
+    return table.concat(tried, separator)
+  end
+  return separator .. table.concat(tried, separator)
+end
+
+local searchers = package.searchers or package.loaders
+table.insert(searchers, 2, searcher)
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_loader_source() {
+        let dir = std::env::temp_dir()
+            .canonicalize()
+            .unwrap()
+            .join("luap_loader_source");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let project = dir.join("app");
+        fs::create_dir_all(project.join("src/app")).unwrap();
+        fs::create_dir_all(project.join("lua_modules/json")).unwrap();
+        fs::write(project.join("lua_modules/json/json.lua"), "").unwrap();
+        // a dependency with a `path` lives outside of lua_modules
+        fs::create_dir_all(dir.join("mylib/lib")).unwrap();
+        fs::write(dir.join("mylib/lib/mylib.lua"), "").unwrap();
+        fs::write(dir.join("mylib/lib/json.lua"), "").unwrap();
+
+        let library_paths = vec![
+            project.join("src"),
+            project.join("lua_modules/json"),
+            dir.join("mylib/lib"),
+        ];
+        let native_path = project.join("lua_modules/.lib/luajit");
+        let source = loader_source(&project.join("lua_modules"), &library_paths, &native_path);
+        assert!(source
+            .contains("local roots = {\n  \"../src\",\n  \"json\",\n  \"../../mylib/lib\",\n}\n"));
+        assert!(source.contains(
+            "local modules = {\n  [\"app\"] = { 1 },\n  [\"json\"] = { 2, 3 },\n  [\"mylib\"] = { 3 },\n}\n"
+        ));
+        assert!(source.contains("local native = \".lib/luajit\"\n"));
+    }
+}
@@ -1,11 +1,30 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use lua_workspace_config::workspace_config::{target_lua_version, BuildConfig, WorkspaceConfig};
+use lua_workspace_config::workspace_lock::WorkspaceLock;
+
+use layout::InstallDir;
+
 pub mod install;
+pub mod layout;
+pub mod list;
+pub mod loader;
 pub mod add;
+pub mod build;
 pub mod check;
+pub mod env;
+pub mod exec;
+pub mod global;
 pub mod init;
 pub mod remove;
+pub mod run;
+pub mod schema;
+pub mod shim;
+pub mod test;
 pub mod update;
+pub mod verify_manifest;
+pub mod workspace;
 
 pub(crate) fn find_library_path(base_path: &Path, path: Option<String>) -> PathBuf {
     if let Some(path) = path {
@@ -19,20 +38,220 @@ pub(crate) fn find_library_path(base_path: &Path, path: Option<String>) -> PathB
         if lib_path.exists() {
             return lib_path;
         }
-        return base_path.to_path_buf();
+        base_path.to_path_buf()
     }
 }
 
-pub(crate) fn find_repo_path(name: &str, version: Option<String>, path: Option<String>) -> PathBuf {
-    if let Some(path) = path {
-        return PathBuf::from(path);
+/// Reads the library path a checked out package declares in its own package.toml.
+pub(crate) fn read_library_path(repo_path: &Path) -> Option<String> {
+    let package_path = repo_path.join("package.toml");
+    let config = WorkspaceConfig::parse_toml_file(package_path.to_str()?).ok()?;
+    config.package?.path
+}
+
+/// Reads the commands a checked out package declares in its own package.toml.
+pub(crate) fn read_bin_config(repo_path: &Path) -> Option<HashMap<String, String>> {
+    let package_path = repo_path.join("package.toml");
+    let config = WorkspaceConfig::parse_toml_file(package_path.to_str()?).ok()?;
+    config.bin
+}
+
+/// Reads the C modules a checked out package declares in its own package.toml.
+pub(crate) fn read_build_config(repo_path: &Path) -> Option<BuildConfig> {
+    let package_path = repo_path.join("package.toml");
+    let config = WorkspaceConfig::parse_toml_file(package_path.to_str()?).ok()?;
+    config.build
+}
+
+/// Library directories of the root package and of every package in the lock,
+/// the places `require` has to look in.
+pub(crate) fn installed_library_paths(
+    base_path: &Path,
+    config: &WorkspaceConfig,
+    lock: Option<&WorkspaceLock>,
+    target: Option<&str>,
+) -> Vec<PathBuf> {
+    let install_dir = InstallDir::new(config);
+    let mut paths = Vec::new();
+    if let Some(package) = &config.package {
+        paths.push(find_library_path(base_path, package.path.clone()));
     }
+    for package in lock.iter().flat_map(|lock| &lock.packages) {
+        let repo_path = base_path.join(install_dir.repo_path(&package.name, package.path.clone()));
+        let library = read_library_path(&repo_path);
+        let path = install_dir.library_path(&repo_path, library, target);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
 
-    let mut base_path = PathBuf::from("lua_modules");
-    base_path.push(name);
-    if let Some(version) = version {
-        base_path.push(version);
+/// The directory of the nearest package.toml, from `start` up.
+pub(crate) fn find_package_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join("package.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// The nearest package, or the root of the workspace when that package is
+/// one of its members.
+pub(crate) fn find_project_root(start: &Path) -> Option<PathBuf> {
+    let root = find_package_root(start)?;
+    Some(workspace::find_workspace_root(&root).unwrap_or(root))
+}
+
+/// Makes the project the current directory, so that luap works the same from
+/// anywhere inside of it. Outside of a project nothing changes.
+pub fn enter_project_root() {
+    let Ok(current_dir) = std::env::current_dir() else {
+        return;
+    };
+    if let Some(root) = find_project_root(&current_dir) {
+        if root != current_dir {
+            if let Err(e) = std::env::set_current_dir(&root) {
+                eprintln!("Failed to enter {}: {}", root.display(), e);
+                std::process::exit(1);
+            }
+        }
     }
-    
-    base_path
-}
\ No newline at end of file
+}
+
+/// Search path templates of `LUA_PATH` and `LUA_CPATH` that find the built
+/// C modules and the modules of the library paths.
+pub(crate) fn lua_search_paths(
+    library_paths: &[PathBuf],
+    native_path: &Path,
+) -> (Vec<String>, Vec<String>) {
+    let extension = if cfg!(windows) { "dll" } else { "so" };
+    let mut lua_path = Vec::new();
+    let mut lua_cpath = vec![format!("{}/?.{}", native_path.to_string_lossy(), extension)];
+    for path in library_paths {
+        let path = path.to_string_lossy();
+        lua_path.push(format!("{}/?.lua", path));
+        lua_path.push(format!("{}/?/init.lua", path));
+        lua_cpath.push(format!("{}/?.{}", path, extension));
+    }
+    (lua_path, lua_cpath)
+}
+
+/// Puts the templates in front of what the first of the variables that is
+/// set holds now, leaving out the ones it already has so that setting it
+/// again changes nothing.
+fn prepend_search_path(templates: &[String], vars: &[&str]) -> String {
+    let current = vars
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()));
+    match current {
+        Some(value) => {
+            let rest: Vec<&str> = value
+                .split(';')
+                .filter(|t| t.is_empty() || !templates.iter().any(|own| own == t))
+                .collect();
+            let mut paths = templates.to_vec();
+            paths.push(rest.join(";"));
+            paths.join(";")
+        }
+        // `;;` is where Lua puts its default path
+        None => format!("{};;", templates.join(";")),
+    }
+}
+
+/// `LUA_PATH` and `LUA_CPATH` for running Lua in the project, with every
+/// installed package of the lock and the C modules built for its target.
+/// Lua 5.2 and later read `LUA_PATH_5_4` and the like before them, so those
+/// are set as well for the target, or for all of them without one.
+pub(crate) fn lua_environment(
+    base_path: &Path,
+    config: &WorkspaceConfig,
+    lock: Option<&WorkspaceLock>,
+) -> Vec<(String, String)> {
+    let target = environment_target(config, lock);
+    search_path_environment(
+        &installed_library_paths(base_path, config, lock, target),
+        &base_path.join(InstallDir::new(config).native_path(target)),
+        target,
+        prepend_search_path,
+    )
+}
+
+/// The same variables with only the paths of the project and the defaults of
+/// Lua, for scripts that must not depend on the environment they were
+/// written in.
+pub(crate) fn isolated_lua_environment(
+    base_path: &Path,
+    config: &WorkspaceConfig,
+    lock: Option<&WorkspaceLock>,
+) -> Vec<(String, String)> {
+    let target = environment_target(config, lock);
+    isolated_environment(
+        &installed_library_paths(base_path, config, lock, target),
+        &base_path.join(InstallDir::new(config).native_path(target)),
+        target,
+    )
+}
+
+pub(crate) fn isolated_environment(
+    library_paths: &[PathBuf],
+    native_path: &Path,
+    target: Option<&str>,
+) -> Vec<(String, String)> {
+    search_path_environment(library_paths, native_path, target, |templates, _| {
+        format!("{};;", templates.join(";"))
+    })
+}
+
+fn environment_target<'a>(
+    config: &'a WorkspaceConfig,
+    lock: Option<&'a WorkspaceLock>,
+) -> Option<&'a str> {
+    lock.and_then(|lock| lock.target.as_deref())
+        .or_else(|| config.package.as_ref()?.default_target.as_deref())
+}
+
+fn search_path_environment(
+    library_paths: &[PathBuf],
+    native_path: &Path,
+    target: Option<&str>,
+    search_path: impl Fn(&[String], &[&str]) -> String,
+) -> Vec<(String, String)> {
+    let (lua_path, lua_cpath) = lua_search_paths(library_paths, native_path);
+
+    let versions = match target {
+        Some(target) => vec![target_lua_version(target).unwrap_or("5.1")],
+        None => vec!["5.2", "5.3", "5.4"],
+    };
+    let mut suffixes = vec![String::new()];
+    for version in versions.into_iter().filter(|version| *version != "5.1") {
+        suffixes.push(format!("_{}", version.replace('.', "_")));
+    }
+    let mut vars = Vec::new();
+    for (name, templates) in [("LUA_PATH", &lua_path), ("LUA_CPATH", &lua_cpath)] {
+        for suffix in &suffixes {
+            // a versioned variable that is not set yet takes over the plain one
+            let var = format!("{}{}", name, suffix);
+            let value = search_path(templates, &[&var, name]);
+            vars.push((var, value));
+        }
+    }
+    vars
+}
+
+/// The interpreter that runs Lua scripts for a target.
+pub(crate) fn lua_interpreter(target: Option<&str>) -> &'static str {
+    if target == Some("luajit") {
+        "luajit"
+    } else {
+        "lua"
+    }
+}
+
+/// `PATH` with the wrappers of the installed commands in front of it.
+pub(crate) fn path_with_bin(base_path: &Path, config: &WorkspaceConfig) -> (String, String) {
+    let bin_path = base_path.join(InstallDir::new(config).bin_path());
+    let current = std::env::var_os("PATH").unwrap_or_default();
+    let paths = std::iter::once(bin_path).chain(std::env::split_paths(&current));
+    let path = std::env::join_paths(paths).unwrap_or(current);
+    ("PATH".to_string(), path.to_string_lossy().to_string())
+}
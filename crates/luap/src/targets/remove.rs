@@ -1,69 +1,97 @@
 use std::path::Path;
 
-use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
+use lua_workspace_config::manifest_edit::{ManifestEditor, DEPENDENCIES, DEV_DEPENDENCIES};
+use lua_workspace_config::workspace_config::WorkspaceConfig;
 
-use crate::lock_file::gen_lock_file;
-
-use super::find_repo_path;
+use super::install::{try_install_package, InstallOptions};
+use super::layout::InstallDir;
+use super::{find_package_root, find_project_root};
 
 pub(crate) fn remove_package(package_name: &str) {
-    let path = Path::new("package.toml");
+    inner_remove_package(DEPENDENCIES, package_name);
+    eprintln!("Package {} removed!", package_name);
+}
+
+pub(crate) fn remove_dev_package(package_name: &str) {
+    inner_remove_package(DEV_DEPENDENCIES, package_name);
+    eprintln!("Dev package {} removed!", package_name);
+}
 
-    if !path.exists() {
+fn inner_remove_package(table: &[&str], package_name: &str) {
+    let current_dir = std::env::current_dir().unwrap();
+    let Some(package_root) = find_package_root(&current_dir) else {
         eprintln!("package.toml not found");
-        return;
-    }
+        std::process::exit(1);
+    };
+    // a member of a workspace is edited, and installed with the rest of it
+    let project_root = find_project_root(&package_root).unwrap_or(package_root.clone());
 
-    let mut config = WorkspaceConfig::parse_toml_file(path.to_str().unwrap()).unwrap();
-    if let Some(deps) = &config.dependencies {
-        if let Some(dep) = deps.get(package_name) {
-            let version = dep.get_version();
-            let path = dep.get_path();
-            let repo_path = find_repo_path(package_name, version.clone(), path.clone());
-            let repo = repo_path.to_str().unwrap();
-            if Path::new(repo).exists() {
-                std::fs::remove_dir_all(repo).unwrap_or_else(|err| {
-                    eprintln!("Failed to remove directory {}: {}", repo, err);
-                });
-            }
+    let path = package_root.join("package.toml");
+    let (config, mut editor) = read_package_toml(&path);
+    let dep = if table == DEV_DEPENDENCIES {
+        config.get_dev_dependency(package_name)
+    } else {
+        config.get_dependency(package_name)
+    };
+    let Some(dep) = dep else {
+        eprintln!(
+            "Package {} not found in [{}] of {}",
+            package_name,
+            table.join("."),
+            path.display()
+        );
+        std::process::exit(1);
+    };
 
-            config.remove_dependency(package_name);
-        }
+    // packages are installed where the root of the workspace says
+    let install_dir = if project_root == package_root {
+        InstallDir::new(&config)
+    } else {
+        InstallDir::new(&read_package_toml(&project_root.join("package.toml")).0)
+    };
+    let repo_path = project_root.join(install_dir.repo_path(package_name, dep.get_path()));
+    // a local package is only used, it is not ours to delete
+    if dep.get_local_path().is_none() && repo_path.exists() {
+        std::fs::remove_dir_all(&repo_path).unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to remove directory {}: {}",
+                repo_path.display(),
+                err
+            );
+        });
     }
 
-
-    config.to_toml_file(path.to_str().unwrap()).expect("Failed to write package.toml");
-    gen_lock_file(path).unwrap_or_else(|err| {
-        eprintln!("Failed to generate lock file: {}", err);
-    });
-
-    eprintln!("Package {} removed!", package_name);
+    editor.remove_dependency(table, package_name);
+    if let Err(e) = editor.write_toml_file(path.to_str().unwrap()) {
+        eprintln!("Failed to write package.toml: {}", e);
+        std::process::exit(1);
+    }
+    relock(&project_root);
 }
 
-pub(crate) fn remove_dev_package(package_name: &str) {
-    let path = Path::new("package.toml");
-
-    if !path.exists() {
-        eprintln!("package.toml not found");
-        return;
+fn read_package_toml(path: &Path) -> (WorkspaceConfig, ManifestEditor) {
+    let path = path.to_str().unwrap();
+    let manifest = WorkspaceConfig::parse_toml_file(path)
+        .and_then(|config| Ok((config, ManifestEditor::parse_toml_file(path)?)));
+    match manifest {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Failed to parse package.toml: {}", e);
+            std::process::exit(1);
+        }
     }
+}
 
-    let mut config = WorkspaceConfig::parse_toml_file(path.to_str().unwrap()).unwrap();
-    if let Some(deps) = &config.dev_dependencies {
-        if let Some(dep) = deps.get(package_name) {
-            match dep {
-                Dependency::Detailed { version, path, .. } => {
-                    let repo_path = find_repo_path(package_name, version.clone(), path.clone());
-                    let repo = repo_path.to_str().unwrap();
-                    if Path::new(repo).exists() {
-                        std::fs::remove_dir_all(repo).unwrap_or_else(|err| {
-                            eprintln!("Failed to remove directory {}: {}", repo, err);
-                        });
-                    }
-                }
-                _ => {}
-            }
-            config.remove_dev_dependency(package_name);
-        }
+// resolving again drops the removed package, and everything only it needed,
+// from package.lock
+fn relock(project_root: &Path) {
+    if let Err(e) = std::env::set_current_dir(project_root) {
+        eprintln!("Failed to enter {}: {}", project_root.display(), e);
+        std::process::exit(1);
+    }
+    let mut results: Vec<String> = Vec::new();
+    if !try_install_package(project_root, &InstallOptions::default(), &mut results) {
+        eprintln!("Failed to update package.lock");
+        std::process::exit(1);
     }
 }
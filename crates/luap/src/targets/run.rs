@@ -0,0 +1,127 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::lock_file::read_lock_file;
+
+use super::verify_manifest::read_manifest;
+use super::{lua_environment, path_with_bin};
+
+pub fn run_script(name: Option<String>, args: Vec<String>) {
+    let base_path = std::env::current_dir().unwrap();
+    match try_run_script(&base_path, name.as_deref(), &args) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs a script of package.toml and returns its exit code, or lists the
+/// scripts when no name is given.
+fn try_run_script(base_path: &Path, name: Option<&str>, args: &[String]) -> Result<i32, String> {
+    let package_path = base_path.join("package.toml");
+    if !package_path.exists() {
+        return Err("package.toml not found".to_string());
+    }
+    let config = read_manifest(&package_path)
+        .map_err(|e| format!("Failed to parse package.toml:\n{}", e))?;
+    let scripts = config.scripts.clone().unwrap_or_default();
+    let mut names: Vec<&String> = scripts.keys().collect();
+    names.sort();
+
+    let Some(name) = name else {
+        if names.is_empty() {
+            eprintln!("No scripts in package.toml");
+        }
+        for name in names {
+            println!("{}: {}", name, scripts[name]);
+        }
+        return Ok(0);
+    };
+    let Some(script) = scripts.get(name) else {
+        let available = names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!(
+            "Script `{}` not found in package.toml, available scripts: {}",
+            name, available
+        ));
+    };
+
+    let lock =
+        read_lock_file(base_path).map_err(|e| format!("Failed to read package.lock: {}", e))?;
+
+    eprintln!("> {}", script);
+    let status = shell_command(name, script, args)
+        .current_dir(base_path)
+        .envs(lua_environment(base_path, &config, lock.as_ref()))
+        .envs([path_with_bin(base_path, &config)])
+        .status()
+        .map_err(|e| format!("Failed to run script `{}`: {}", name, e))?;
+    // a script killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+/// The script runs in the shell, with the extra arguments appended to it.
+fn shell_command(name: &str, script: &str, args: &[String]) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(script).args(args);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", script))
+            .arg(name)
+            .args(args);
+        command
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_run_script() {
+        let dir = std::env::temp_dir()
+            .canonicalize()
+            .unwrap()
+            .join("luap_run_script");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("package.toml"),
+            r#"
+            [package]
+            path = "src"
+
+            [scripts]
+            paths = "printf '%s\n' \"$LUA_PATH\" \"$PWD\" > out.txt; printf '[%s]' >> out.txt"
+            fail = "exit 3"
+            "#,
+        )
+        .unwrap();
+
+        let args = vec!["a b".to_string(), "c".to_string()];
+        assert_eq!(try_run_script(&dir, Some("paths"), &args), Ok(0));
+        let out = fs::read_to_string(dir.join("out.txt")).unwrap();
+        let src = dir.join("src");
+        let src = src.to_str().unwrap();
+        assert!(out.starts_with(&format!("{}/?.lua;{}/?/init.lua;", src, src)));
+        assert!(out.ends_with(&format!("\n{}\n[a b][c]", dir.to_str().unwrap())));
+
+        assert_eq!(try_run_script(&dir, Some("fail"), &[]), Ok(3));
+        assert!(try_run_script(&dir, Some("missing"), &[])
+            .unwrap_err()
+            .ends_with("available scripts: fail, paths"));
+    }
+}
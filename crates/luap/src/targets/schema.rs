@@ -0,0 +1,10 @@
+use lua_workspace_config::schema::{lock_schema, manifest_schema};
+
+pub fn print_schema(lock: bool) {
+    let schema = if lock {
+        lock_schema()
+    } else {
+        manifest_schema()
+    };
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::env::format_env;
+
+/// Written into every shim, followed by the name of the package it runs.
+const SHIM_MARKER: &str = "luap package:";
+
+/// A command of a package, the script that runs it and the package it
+/// belongs to.
+pub(crate) struct Command {
+    pub package: String,
+    pub name: String,
+    pub script: PathBuf,
+}
+
+fn shim_path(bin_path: &Path, command: &str) -> PathBuf {
+    if cfg!(windows) {
+        bin_path.join(format!("{}.cmd", command))
+    } else {
+        bin_path.join(command)
+    }
+}
+
+/// The package a shim was written for, none for a file luap did not write.
+fn shim_owner(path: &Path) -> Option<String> {
+    let source = std::fs::read_to_string(path).ok()?;
+    source.lines().find_map(|line| {
+        let (_, owner) = line.split_once(SHIM_MARKER)?;
+        Some(owner.trim().to_string())
+    })
+}
+
+/// Commands in `bin_path` mapped to the package that owns them.
+pub(crate) fn shim_owners(bin_path: &Path) -> BTreeMap<String, String> {
+    let Ok(entries) = std::fs::read_dir(bin_path) else {
+        return BTreeMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let command = match cfg!(windows) {
+                true => file_name.strip_suffix(".cmd")?.to_string(),
+                false => file_name,
+            };
+            Some((command, shim_owner(&entry.path())?))
+        })
+        .collect()
+}
+
+pub(crate) fn remove_shims(bin_path: &Path, package: &str) -> Result<(), String> {
+    for (command, owner) in shim_owners(bin_path) {
+        if owner == package {
+            let path = shim_path(bin_path, &command);
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a shim for the command into `bin_path`, a shim of another package
+/// or a file luap did not write is never replaced.
+pub(crate) fn write_shim(
+    bin_path: &Path,
+    command: &Command,
+    lua: &str,
+    env: &[(String, String)],
+) -> Result<(), String> {
+    let name = &command.name;
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(format!(
+            "`{}` of {} is not a command name",
+            name, command.package
+        ));
+    }
+    let path = shim_path(bin_path, name);
+    if path.exists() {
+        match shim_owner(&path) {
+            Some(owner) if owner == command.package => {}
+            Some(owner) => {
+                return Err(format!(
+                    "`{}` is already installed by {}, uninstall it first",
+                    name, owner
+                ))
+            }
+            None => return Err(format!("{} already exists", path.display())),
+        }
+    }
+    std::fs::create_dir_all(bin_path).map_err(|e| e.to_string())?;
+    std::fs::write(&path, shim_source(command, lua, env)?)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to make {} executable: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Replaces everything in `bin_path` with shims for the commands. Of two
+/// commands with the same name, the first one is kept.
+pub(crate) fn replace_shims(
+    bin_path: &Path,
+    commands: &[Command],
+    lua: &str,
+    env: &[(String, String)],
+) -> Result<(), String> {
+    if bin_path.exists() {
+        std::fs::remove_dir_all(bin_path)
+            .map_err(|e| format!("Failed to remove {}: {}", bin_path.display(), e))?;
+    }
+    let mut owners: BTreeMap<&str, &str> = BTreeMap::new();
+    for command in commands {
+        if let Some(owner) = owners.get(command.name.as_str()) {
+            eprintln!(
+                "Warning: `{}` of {} is also a command of {}, the one of {} is used",
+                command.name, command.package, owner, owner
+            );
+            continue;
+        }
+        write_shim(bin_path, command, lua, env)?;
+        owners.insert(&command.name, &command.package);
+    }
+    Ok(())
+}
+
+/// A script that runs the command with the search paths of `env`, whatever
+/// the environment of the caller holds.
+fn shim_source(command: &Command, lua: &str, env: &[(String, String)]) -> Result<String, String> {
+    let script = command.script.to_string_lossy();
+    if cfg!(windows) {
+        let mut source = format!("@echo off\r\nrem {} {}\r\n", SHIM_MARKER, command.package);
+        for (var, value) in env {
+            source.push_str(&format!("set \"{}={}\"\r\n", var, value));
+        }
+        source.push_str(&format!("{} \"{}\" %*\r\n", lua, script));
+        return Ok(source);
+    }
+    Ok(format!(
+        "#!/bin/sh\n# {} {}\n{}exec {} '{}' \"$@\"\n",
+        SHIM_MARKER,
+        command.package,
+        format_env(env, "bash")?,
+        lua,
+        script.replace('\'', r"'\''")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shim_source() {
+        let command = Command {
+            package: "busted".to_string(),
+            name: "busted".to_string(),
+            script: PathBuf::from("/tools/bin/busted"),
+        };
+        let env = vec![("LUA_PATH".to_string(), "/tools/?.lua;;".to_string())];
+        let source = shim_source(&command, "lua", &env).unwrap();
+        if cfg!(windows) {
+            assert!(source.contains("set \"LUA_PATH=/tools/?.lua;;\""));
+        } else {
+            assert_eq!(
+                source,
+                "#!/bin/sh\n# luap package: busted\nexport LUA_PATH='/tools/?.lua;;'\nexec lua '/tools/bin/busted' \"$@\"\n"
+            );
+        }
+
+        let dir = std::env::temp_dir().join("luap_shim_owner");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        write_shim(&dir, &command, "lua", &env).unwrap();
+        std::fs::write(shim_path(&dir, "other"), "#!/bin/sh\n").unwrap();
+        assert_eq!(
+            shim_owners(&dir),
+            BTreeMap::from([("busted".to_string(), "busted".to_string())])
+        );
+        let other = Command {
+            package: "other".to_string(),
+            ..command
+        };
+        assert!(write_shim(&dir, &other, "lua", &env).is_err());
+    }
+}
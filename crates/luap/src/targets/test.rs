@@ -0,0 +1,246 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use lua_workspace_config::workspace_config::TestConfig;
+use serde_json::{json, Value};
+
+use crate::lock_file::read_lock_file;
+
+use super::install::{try_install_package, InstallOptions};
+use super::layout::InstallDir;
+use super::{lua_environment, lua_interpreter, path_with_bin};
+use super::verify_manifest::read_manifest;
+
+const DEFAULT_RUNNER: &str = "busted";
+
+pub fn test_package(filters: Vec<String>, args: Vec<String>, json: bool) {
+    let base_path = std::env::current_dir().unwrap();
+    match try_test_package(&base_path, &filters, &args, json) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Installs the package with its dev-dependencies and runs the tests,
+/// returning the exit code of the runner.
+fn try_test_package(
+    base_path: &Path,
+    filters: &[String],
+    args: &[String],
+    json: bool,
+) -> Result<i32, String> {
+    let package_path = base_path.join("package.toml");
+    if !package_path.exists() {
+        return Err("package.toml not found".to_string());
+    }
+    // dev-dependencies hold the runner and whatever the tests require
+    if !try_install_package(base_path, &InstallOptions::default(), &mut Vec::new()) {
+        return Err("Install package failed".to_string());
+    }
+    let config = read_manifest(&package_path)
+        .map_err(|e| format!("Failed to parse package.toml:\n{}", e))?;
+    let lock =
+        read_lock_file(base_path).map_err(|e| format!("Failed to read package.lock: {}", e))?;
+    let target = lock.as_ref().and_then(|lock| lock.target.clone());
+
+    let test = config.test.clone().unwrap_or(TestConfig {
+        runner: None,
+        args: None,
+        filter: None,
+    });
+    let runner = test
+        .runner
+        .clone()
+        .unwrap_or_else(|| DEFAULT_RUNNER.to_string());
+    let busted = runner.split_whitespace().next() == Some("busted");
+
+    let install_dir = InstallDir::new(&config);
+    let mut command = runner_command(base_path, &install_dir, &runner, target.as_deref())?;
+    command.args(test.args.iter().flatten());
+    command.args(filter_args(&test, busted, filters));
+    if json && busted {
+        command.arg("--output=json");
+    }
+    command
+        .args(args)
+        .current_dir(base_path)
+        .envs(lua_environment(base_path, &config, lock.as_ref()))
+        .envs([path_with_bin(base_path, &config)]);
+
+    let not_found = |e: std::io::Error| {
+        if e.kind() == ErrorKind::NotFound {
+            format!(
+                "Test runner `{}` not found, add it to [dev-dependencies] or install it",
+                runner
+            )
+        } else {
+            format!("Failed to run `{}`: {}", runner, e)
+        }
+    };
+    if !json {
+        let status = command.status().map_err(not_found)?;
+        return Ok(status.code().unwrap_or(1));
+    }
+
+    let start = Instant::now();
+    let output = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(not_found)?;
+    let code = output.status.code().unwrap_or(1);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results = if busted {
+        busted_results(&stdout)
+    } else {
+        None
+    };
+    // stdout is for the summary, whatever else the runner printed goes to stderr
+    for line in stdout.lines() {
+        if results.is_none() || serde_json::from_str::<Value>(line).is_err() {
+            eprintln!("{}", line);
+        }
+    }
+    let summary = summary(&runner, code, start.elapsed().as_secs_f64(), results);
+    println!("{}", summary);
+    Ok(code)
+}
+
+/// A runner installed as a dev-dependency is run from `lua_modules` with the
+/// Lua of the target, anything else is looked up on `PATH`.
+fn runner_command(
+    base_path: &Path,
+    install_dir: &InstallDir,
+    runner: &str,
+    target: Option<&str>,
+) -> Result<Command, String> {
+    let mut words = runner.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| "`runner` of [test] is empty".to_string())?;
+    let script = base_path
+        .join(install_dir.repo_path(program, None))
+        .join("bin")
+        .join(program);
+    let mut command = if script.is_file() {
+        let mut command = Command::new(lua_interpreter(target));
+        command.arg(script);
+        command
+    } else {
+        Command::new(program)
+    };
+    command.args(words);
+    Ok(command)
+}
+
+fn filter_args(test: &TestConfig, busted: bool, filters: &[String]) -> Vec<String> {
+    let flag = test
+        .filter
+        .clone()
+        .or_else(|| busted.then(|| "--filter".to_string()));
+    match flag {
+        Some(flag) => filters
+            .iter()
+            .flat_map(|filter| [flag.clone(), filter.clone()])
+            .collect(),
+        None => filters.to_vec(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct TestResults {
+    passed: usize,
+    failed: usize,
+    errors: usize,
+    pending: usize,
+    /// Names of the tests that failed or raised an error.
+    failures: Vec<String>,
+}
+
+/// Reads the `--output=json` report of busted.
+fn busted_results(stdout: &str) -> Option<TestResults> {
+    // tests may print before the report, which is the last line
+    let stdout = stdout.trim();
+    let report: Value = serde_json::from_str(stdout)
+        .ok()
+        .or_else(|| serde_json::from_str(stdout.lines().last()?).ok())?;
+    let tests = |key: &str| report.get(key).and_then(Value::as_array);
+    let count = |key: &str| tests(key).map_or(0, Vec::len);
+    let failures = ["failures", "errors"]
+        .iter()
+        .flat_map(|key| tests(key).into_iter().flatten())
+        .filter_map(|test| test.get("name")?.as_str().map(str::to_string))
+        .collect();
+    Some(TestResults {
+        passed: tests("successes")?.len(),
+        failed: count("failures"),
+        errors: count("errors"),
+        pending: count("pending"),
+        failures,
+    })
+}
+
+/// The line `luap test --json` prints, counts are null when the runner does
+/// not report them.
+fn summary(runner: &str, code: i32, duration: f64, results: Option<TestResults>) -> Value {
+    let mut summary = json!({
+        "runner": runner,
+        "exit_code": code,
+        "success": code == 0,
+        "duration": (duration * 1000.0).round() / 1000.0,
+        "passed": null,
+        "failed": null,
+        "errors": null,
+        "pending": null,
+        "failures": [],
+    });
+    if let Some(results) = results {
+        summary["passed"] = json!(results.passed);
+        summary["failed"] = json!(results.failed);
+        summary["errors"] = json!(results.errors);
+        summary["pending"] = json!(results.pending);
+        summary["failures"] = json!(results.failures);
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busted_summary() {
+        let stdout = r#"debug output of a test
+{"successes":[{"name":"json encodes"},{"name":"json decodes"}],"failures":[{"name":"json round trips","message":"expected 1"}],"errors":[],"pending":[{"name":"todo"}],"duration":0.01}
+"#;
+        let results = busted_results(stdout).unwrap();
+        assert_eq!(
+            results,
+            TestResults {
+                passed: 2,
+                failed: 1,
+                errors: 0,
+                pending: 1,
+                failures: vec!["json round trips".to_string()],
+            }
+        );
+        let summary = summary("busted", 1, 0.25, Some(results));
+        assert_eq!(summary["passed"], 2);
+        assert_eq!(summary["success"], false);
+        assert!(busted_results("not json").is_none());
+
+        let test = TestConfig {
+            runner: None,
+            args: None,
+            filter: None,
+        };
+        let filters = vec!["json".to_string()];
+        assert_eq!(filter_args(&test, true, &filters), vec!["--filter", "json"]);
+        assert_eq!(filter_args(&test, false, &filters), vec!["json"]);
+    }
+}
@@ -1,11 +1,15 @@
 use std::path::Path;
 
-use github_package::{update_to_latest, update_to_special_version};
-use lua_workspace_config::workspace_config::{GithubDependency, WorkspaceConfig};
+use lua_workspace_config::manifest_edit::{
+    ManifestEditor, DEPENDENCIES, DEV_DEPENDENCIES, WORKSPACE_DEPENDENCIES,
+};
+use lua_workspace_config::workspace_config::{Dependency, WorkspaceConfig};
+use lua_workspace_config::workspace_lock::{LockedPackage, SourceKind};
 
-use crate::lock_file::gen_lock_file;
+use crate::lock_file::read_lock_file;
 
-use super::find_repo_path;
+use super::install::{try_install_package, InstallOptions};
+use super::{find_package_root, find_project_root};
 
 pub fn update_package(
     package_name: Option<String>,
@@ -13,116 +17,186 @@ pub fn update_package(
     tag: Option<String>,
     hash: Option<String>,
 ) {
-    let path = Path::new("package.toml");
-
-    if !path.exists() {
+    let current_dir = std::env::current_dir().unwrap();
+    let Some(package_root) = find_package_root(&current_dir) else {
         eprintln!("package.toml not found");
-        return;
-    }
-
-    let mut config = WorkspaceConfig::parse_toml_file(path.to_str().unwrap()).unwrap();
+        std::process::exit(1);
+    };
+    // a member of a workspace is edited, and installed with the rest of it
+    let base_path = find_project_root(&package_root).unwrap_or(package_root.clone());
+    let member_path = package_root.join("package.toml");
+    let root_path = base_path.join("package.toml");
+    let config = read_package_toml(&member_path);
+    let root_config = read_package_toml(&root_path);
 
+    let mut options = InstallOptions::default();
+    let mut original_manifest = None;
     if let Some(package_name) = package_name {
-        update_one_package(&mut config, &package_name, branch, tag, hash);
-    } else {
-        update_all_package(&mut config);
-    }
-    gen_lock_file(path).unwrap_or_else(|err| {
-        eprintln!("Failed to generate lock file: {}", err);
-    });
+        let workspace_deps = root_config
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.dependencies.as_ref());
+        // an inherited dependency is pinned in the workspace, for every
+        // member that uses it
+        let declared = [
+            (
+                &member_path,
+                DEPENDENCIES,
+                config.get_dependency(&package_name),
+            ),
+            (
+                &member_path,
+                DEV_DEPENDENCIES,
+                config.get_dev_dependency(&package_name),
+            ),
+            (
+                &root_path,
+                WORKSPACE_DEPENDENCIES,
+                workspace_deps.and_then(|deps| deps.get(&package_name)),
+            ),
+        ];
+        let direct = declared.iter().find_map(|(path, table, dep)| {
+            dep.filter(|dep| !dep.is_workspace())
+                .map(|dep| (path.as_path(), *table, dep))
+        });
+        // transitive dependencies are only known to the lock file
+        let locked = match find_locked_package(&base_path, &package_name) {
+            Ok(package) => package,
+            Err(e) => {
+                eprintln!("Failed to read package.lock: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if direct.is_none() && locked.is_none() {
+            eprintln!(
+                "Package {} not found in dependencies, dev_dependencies or package.lock",
+                package_name
+            );
+            std::process::exit(1);
+        }
 
-    eprintln!("Update package success");
-}
+        if branch.is_some() || tag.is_some() || hash.is_some() {
+            if let Some((path, table, dep)) = direct {
+                if let Some(local_path) = dep.get_local_path() {
+                    refuse_pin(&package_name, &local_path);
+                }
+                if let Some(archive) = dep.get_archive_dependency() {
+                    refuse_pin(&package_name, &archive.url);
+                }
+                if let Some(rock) = dep.get_rock_dependency() {
+                    refuse_pin(&package_name, &format!("rock {}", rock.name));
+                }
 
-fn update_all_package(config: &mut WorkspaceConfig) {
-    if let Some(dependencies) = &mut config.dependencies {
-        let package_names: Vec<String> = dependencies.keys().cloned().collect();
-        for name in package_names {
-            update_one_package(config, &name, None, None, None);
+                // a direct dependency is pinned where it is declared, next to its
+                // version requirement, which the new revision still has to meet
+                let dep = Dependency::Detailed {
+                    url: Some(dep.get_url()),
+                    tag,
+                    branch,
+                    hash,
+                    version: dep.get_version(),
+                    path: dep.get_path(),
+                    archive: None,
+                    sha256: None,
+                    strip_prefix: None,
+                    rock: None,
+                    optional: None,
+                    features: None,
+                    package: None,
+                };
+                original_manifest = std::fs::read_to_string(path)
+                    .ok()
+                    .map(|manifest| (path.to_path_buf(), manifest));
+                if let Err(e) = pin_dependency(path, table, &package_name, &dep) {
+                    eprintln!("Failed to write package.toml: {}", e);
+                    std::process::exit(1);
+                }
+            } else if let Some(package) = locked {
+                let url = match package.source {
+                    SourceKind::Path => {
+                        refuse_pin(&package_name, &package.path.unwrap_or_default());
+                    }
+                    SourceKind::Archive | SourceKind::Rock => {
+                        refuse_pin(&package_name, &package.url);
+                    }
+                    SourceKind::Git => package.url,
+                };
+                let dep = Dependency::Detailed {
+                    url: Some(url),
+                    tag,
+                    branch,
+                    hash,
+                    version: None,
+                    path: None,
+                    archive: None,
+                    sha256: None,
+                    strip_prefix: None,
+                    rock: None,
+                    optional: None,
+                    features: None,
+                    package: None,
+                };
+                options.constraints.push((package_name.clone(), dep));
+            }
         }
+        options.update.push(package_name);
+    } else {
+        options.update_all = true;
     }
 
-    if let Some(dev_dependencies) = &config.dev_dependencies {
-        let dev_package_names: Vec<String> = dev_dependencies.keys().cloned().collect();
-        for name in dev_package_names {
-            update_one_package(config, &name, None, None, None);
+    if let Err(e) = std::env::set_current_dir(&base_path) {
+        eprintln!("Failed to enter {}: {}", base_path.display(), e);
+        std::process::exit(1);
+    }
+    let mut results: Vec<String> = Vec::new();
+    if try_install_package(&base_path, &options, &mut results) {
+        eprintln!("Update package success");
+    } else {
+        // a pin that cannot be installed is not kept in package.toml
+        if let Some((path, original_manifest)) = original_manifest {
+            if let Err(e) = std::fs::write(path, original_manifest) {
+                eprintln!("Failed to restore package.toml: {}", e);
+            }
         }
+        eprintln!("Update package failed");
+        std::process::exit(1);
     }
 }
 
-fn update_one_package(
-    config: &mut WorkspaceConfig,
+fn pin_dependency(
+    path: &Path,
+    table: &[&str],
     package_name: &str,
-    branch: Option<String>,
-    tag: Option<String>,
-    hash: Option<String>,
-) {
-    if branch.is_some() || tag.is_some() || hash.is_some() {
-        update_one_package_to_special(config, package_name, branch, tag, hash);
-    } else {
-        update_one_package_to_latest(config, package_name);
-    }
+    dep: &Dependency,
+) -> Result<(), std::io::Error> {
+    let mut editor = ManifestEditor::parse_toml_file(path.to_str().unwrap())?;
+    editor.set_dependency(table, package_name, dep);
+    editor.write_toml_file(path.to_str().unwrap())
 }
 
-fn update_one_package_to_latest(config: &mut WorkspaceConfig, package_name: &str) {
-    let dep = if let Some(dep) = config.get_dependency(package_name) {
-        dep
-    } else if let Some(dep) = config.get_dev_dependency(package_name) {
-        dep
-    } else {
-        eprintln!("Package {} not found in dependencies or dev_dependencies", package_name);
-        return;
-    };
-    let path = dep.get_path();
-    let version = dep.get_version();
-    let repo_path = find_repo_path(package_name, version.clone(), path.clone());
-    match update_to_latest(repo_path.as_path()) {
-        Ok(_) => {
-            eprintln!("Update package {} to latest success", package_name);
-        }
+fn read_package_toml(path: &Path) -> WorkspaceConfig {
+    match WorkspaceConfig::parse_toml_file(path.to_str().unwrap()) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Failed to update package {}: {}", package_name, e);
+            eprintln!("Failed to parse package.toml: {}", e);
+            std::process::exit(1);
         }
     }
 }
 
-fn update_one_package_to_special(
-    config: &mut WorkspaceConfig,
+fn find_locked_package(
+    base_path: &Path,
     package_name: &str,
-    branch: Option<String>,
-    tag: Option<String>,
-    hash: Option<String>,
-) {
-    let dep = if let Some(dep) = config.get_dependency(package_name) {
-        dep
-    } else if let Some(dep) = config.get_dev_dependency(package_name) {
-        dep
-    } else {
-        eprintln!("Package {} not found in dependencies or dev_dependencies", package_name);
-        return;
-    };
-    let path = dep.get_path();
-    let version = dep.get_version();
-    let repo_path = find_repo_path(package_name, version.clone(), path.clone());
-    let repo = repo_path.as_path();
-    if !repo.exists() {
-        eprintln!("Repo path {} not found", repo.to_str().unwrap());
-        return;
-    }
-
-    let github_dep = GithubDependency {
-        url: dep.get_url(),
-        branch,
-        tag,
-        hash,
-    };
+) -> Result<Option<LockedPackage>, std::io::Error> {
+    let lock = read_lock_file(base_path)?;
+    Ok(lock.and_then(|lock| lock.get_package(package_name).cloned()))
+}
 
-    match update_to_special_version(&github_dep, repo) {
-        Ok(_) => {
-            eprintln!("Update package {} to special version success", package_name);
-        }
-        Err(e) => {
-            eprintln!("Failed to update package {}: {}", package_name, e);
-        }
-    }
+// local packages and archives are exactly what package.toml names
+fn refuse_pin(package_name: &str, source: &str) -> ! {
+    eprintln!(
+        "Package {} comes from {}, it has no revision to pin",
+        package_name, source
+    );
+    std::process::exit(1);
 }
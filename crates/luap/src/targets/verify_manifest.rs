@@ -0,0 +1,903 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::{Component, Path};
+
+use github_package::tag::parse_version_req;
+use github_package::{expand_host_alias, parse_repo_url};
+use lua_workspace_config::workspace_config::{
+    target_lua_version, InstallLayout, WorkspaceConfig, INHERITED_KEYS, TARGETS,
+};
+use toml_edit::{ImDocument, Item, Key, TableLike, Value};
+
+const MANIFEST_KEYS: &[&str] = &[
+    "package",
+    "dependencies",
+    "dev-dependencies",
+    "hosts",
+    "rocks",
+    "features",
+    "target",
+    "patch",
+    "scripts",
+    "build",
+    "test",
+    "install",
+    "bin",
+    "workspace",
+];
+const PACKAGE_KEYS: &[&str] = &["name", "version", "path", "lua", "default-target"];
+const DEPENDENCY_KEYS: &[&str] = &[
+    "url",
+    "tag",
+    "branch",
+    "hash",
+    "version",
+    "path",
+    "archive",
+    "sha256",
+    "strip-prefix",
+    "rock",
+    "optional",
+    "features",
+    "package",
+    "workspace",
+];
+const TARGET_KEYS: &[&str] = &["dependencies", "dev-dependencies"];
+const ROCKS_KEYS: &[&str] = &["server"];
+const ROCK_KEYS: &[&str] = &["name", "version"];
+const TEST_KEYS: &[&str] = &["runner", "args", "filter"];
+const BUILD_KEYS: &[&str] = &["modules"];
+const MODULE_KEYS: &[&str] = &["sources", "incdirs", "defines", "libraries", "libdirs"];
+const INSTALL_KEYS: &[&str] = &["dir", "layout"];
+const WORKSPACE_KEYS: &[&str] = &["members", "dependencies"];
+
+pub fn verify_manifest(path: Option<String>) {
+    let path = path.unwrap_or_else(|| "package.toml".to_string());
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let diagnostics = verify_manifest_str(&source);
+    for diagnostic in &diagnostics {
+        eprintln!("{}", render(&path, &source, diagnostic));
+    }
+    match diagnostics.len() {
+        0 => eprintln!("{} is valid", path),
+        1 => {
+            eprintln!("{} has 1 error", path);
+            std::process::exit(1);
+        }
+        n => {
+            eprintln!("{} has {} errors", path, n);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads package.toml, a manifest that does not parse is reported with the
+/// line it fails on.
+pub(crate) fn read_manifest(path: &Path) -> Result<WorkspaceConfig, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    WorkspaceConfig::parse_toml_str(&source).map_err(|e| {
+        let diagnostic = Diagnostic {
+            span: e.span(),
+            message: e.message().to_string(),
+        };
+        render(&path.to_string_lossy(), &source, &diagnostic)
+    })
+}
+
+#[derive(Debug)]
+pub(crate) struct Diagnostic {
+    pub span: Option<Range<usize>>,
+    pub message: String,
+}
+
+pub(crate) fn verify_manifest_str(source: &str) -> Vec<Diagnostic> {
+    let doc = match ImDocument::parse(source) {
+        Ok(doc) => doc,
+        Err(e) => {
+            return vec![Diagnostic {
+                span: e.span(),
+                message: e.message().trim_end().to_string(),
+            }]
+        }
+    };
+
+    let mut verifier = Verifier::default();
+    verifier.manifest(doc.as_table());
+    // anything the checks above do not know about is left to serde
+    if verifier.diagnostics.is_empty() {
+        if let Err(e) = WorkspaceConfig::parse_toml_str(source) {
+            verifier.diagnostics.push(Diagnostic {
+                span: e.span(),
+                message: e.message().trim_end().to_string(),
+            });
+        }
+    }
+    verifier
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.as_ref().map(|span| span.start));
+    verifier.diagnostics
+}
+
+/// Formats a diagnostic the way rustc does, with the line it points at:
+///
+/// ```text
+/// error: unknown key `brnach` in dependency `json`, did you mean `branch`?
+///  --> package.toml:3:28
+///   |
+/// 3 | json = { url = "rxi/json", brnach = "dev" }
+///   |                            ^^^^^^
+/// ```
+pub(crate) fn render(file: &str, source: &str, diagnostic: &Diagnostic) -> String {
+    let Some(span) = &diagnostic.span else {
+        return format!("error: {}\n --> {}", diagnostic.message, file);
+    };
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |pos| pos + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |pos| start + pos);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    let line_number = source[..line_start].matches('\n').count() + 1;
+    let column = source[line_start..start].chars().count() + 1;
+    let end = span.end.clamp(start, line_end);
+    let width = source[start..end].chars().count().max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    format!(
+        "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        diagnostic.message,
+        gutter,
+        file,
+        line_number,
+        column,
+        gutter,
+        line_number,
+        line,
+        gutter,
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+}
+
+/// A relative path that names a directory below the project, not the project
+/// itself.
+fn inside_project(path: &str) -> bool {
+    let mut components = Path::new(path).components();
+    components
+        .clone()
+        .any(|c| matches!(c, Component::Normal(_)))
+        && components.all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+#[derive(Default)]
+struct Verifier {
+    diagnostics: Vec<Diagnostic>,
+    hosts: HashMap<String, String>,
+}
+
+impl Verifier {
+    fn error(&mut self, span: Option<Range<usize>>, message: String) {
+        self.diagnostics.push(Diagnostic { span, message });
+    }
+
+    fn manifest(&mut self, root: &dyn TableLike) {
+        self.known_keys(root, MANIFEST_KEYS, "package.toml");
+
+        // urls of every table may use the aliases
+        if let Some(hosts) = self.table(root, "hosts") {
+            for (alias, url) in hosts.iter() {
+                match url.as_str() {
+                    Some(url) => {
+                        self.hosts.insert(alias.to_string(), url.to_string());
+                    }
+                    None => {
+                        self.error(url.span(), format!("host `{}` must be a url string", alias))
+                    }
+                }
+            }
+        }
+
+        if let Some(package) = self.table(root, "package") {
+            self.package(package);
+        }
+        if let Some(rocks) = self.table(root, "rocks") {
+            self.known_keys(rocks, ROCKS_KEYS, "[rocks]");
+            self.string(rocks, "server", "[rocks]");
+        }
+
+        let mut dependency_names = BTreeSet::new();
+        for table in ["dependencies", "dev-dependencies"] {
+            if let Some(deps) = self.table(root, table) {
+                dependency_names.extend(deps.iter().map(|(name, _)| name.to_string()));
+                self.dependencies(deps);
+            }
+        }
+        if let Some(targets) = self.table(root, "target") {
+            for (target, _) in targets.iter() {
+                let (key, item) = targets.get_key_value(target).unwrap();
+                if target_lua_version(target).is_none() {
+                    let names: Vec<&str> = TARGETS.iter().map(|(name, _)| *name).collect();
+                    self.error(
+                        key.span(),
+                        format!(
+                            "unknown target `{}`, expected one of {}",
+                            target,
+                            names.join(", ")
+                        ),
+                    );
+                }
+                let Some(config) = item.as_table_like() else {
+                    self.error(item.span(), format!("target `{}` must be a table", target));
+                    continue;
+                };
+                self.known_keys(config, TARGET_KEYS, &format!("target `{}`", target));
+                for table in TARGET_KEYS {
+                    if let Some(deps) = self.table(config, table) {
+                        dependency_names.extend(deps.iter().map(|(name, _)| name.to_string()));
+                        self.dependencies(deps);
+                    }
+                }
+            }
+        }
+        if let Some(features) = self.table(root, "features") {
+            self.features(features, &dependency_names);
+        }
+        if let Some(patches) = self.table(root, "patch") {
+            for (url, _) in patches.iter() {
+                let (key, item) = patches.get_key_value(url).unwrap();
+                self.url(key.span(), url);
+                self.dependency(&format!("patch for {}", url), item);
+            }
+        }
+        if let Some(scripts) = self.table(root, "scripts") {
+            for (name, _) in scripts.iter() {
+                self.string(scripts, name, "[scripts]");
+            }
+        }
+        if let Some(build) = self.table(root, "build") {
+            self.build(build);
+        }
+        if let Some(bin) = self.table(root, "bin") {
+            for (name, _) in bin.iter() {
+                self.string(bin, name, "[bin]");
+            }
+        }
+        if let Some(install) = self.table(root, "install") {
+            self.install(install);
+        }
+        if let Some(workspace) = self.table(root, "workspace") {
+            self.workspace(workspace);
+        }
+        if let Some(test) = self.table(root, "test") {
+            self.known_keys(test, TEST_KEYS, "[test]");
+            self.string(test, "runner", "[test]");
+            self.string(test, "filter", "[test]");
+            if let Some(args) = test.get("args") {
+                let strings = args
+                    .as_array()
+                    .is_some_and(|args| args.iter().all(|arg| arg.as_str().is_some()));
+                if !strings {
+                    self.error(
+                        args.span(),
+                        "`args` of [test] must be a list of strings".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn install(&mut self, install: &dyn TableLike) {
+        self.known_keys(install, INSTALL_KEYS, "[install]");
+        if let Some((span, dir)) = self.string(install, "dir", "[install]") {
+            // the directory is emptied and written by luap, it must not be
+            // the project or anything outside of it
+            if !inside_project(&dir) {
+                self.error(
+                    span,
+                    format!(
+                        "`dir` of [install] must be a directory inside the project, not `{}`",
+                        dir
+                    ),
+                );
+            }
+        }
+        if let Some((span, layout)) = self.string(install, "layout", "[install]") {
+            if !InstallLayout::NAMES.contains(&layout.as_str()) {
+                self.error(
+                    span,
+                    format!(
+                        "unknown layout `{}`, expected one of {}",
+                        layout,
+                        InstallLayout::NAMES.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    fn workspace(&mut self, workspace: &dyn TableLike) {
+        self.known_keys(workspace, WORKSPACE_KEYS, "[workspace]");
+        if let Some(deps) = self.table(workspace, "dependencies") {
+            for (name, item) in deps.iter() {
+                let what = format!("dependency `{}` of [workspace.dependencies]", name);
+                match item.as_table_like().and_then(|dep| dep.key("workspace")) {
+                    Some(key) => self.error(
+                        key.span(),
+                        format!("{} cannot be inherited from the workspace itself", what),
+                    ),
+                    None => self.dependency(&what, item),
+                }
+            }
+        }
+        let Some(members) = workspace.get("members") else {
+            return;
+        };
+        let Some(members) = members.as_array() else {
+            self.error(
+                members.span(),
+                "`members` of [workspace] must be a list of directories".to_string(),
+            );
+            return;
+        };
+        for member in members.iter() {
+            match member.as_str() {
+                Some(path) if inside_project(path) => {}
+                Some(path) => self.error(
+                    member.span(),
+                    format!("workspace member `{}` must be a directory inside the project", path),
+                ),
+                None => self.error(
+                    member.span(),
+                    "`members` of [workspace] must be a list of directories".to_string(),
+                ),
+            }
+        }
+    }
+
+    fn build(&mut self, build: &dyn TableLike) {
+        self.known_keys(build, BUILD_KEYS, "[build]");
+        let Some(modules) = self.table(build, "modules") else {
+            return;
+        };
+        for (name, item) in modules.iter() {
+            let what = format!("C module `{}`", name);
+            let Some(module) = item.as_table_like() else {
+                self.error(item.span(), format!("{} must be a table", what));
+                continue;
+            };
+            self.known_keys(module, MODULE_KEYS, &what);
+            if !module.contains_key("sources") {
+                self.error(item.span(), format!("{} has no `sources`", what));
+            }
+            for key in MODULE_KEYS {
+                let Some(list) = module.get(key) else {
+                    continue;
+                };
+                let strings = list
+                    .as_array()
+                    .is_some_and(|list| list.iter().all(|v| v.as_str().is_some()));
+                if !strings {
+                    self.error(
+                        list.span(),
+                        format!("`{}` of {} must be a list of strings", key, what),
+                    );
+                }
+            }
+        }
+    }
+
+    fn package(&mut self, package: &dyn TableLike) {
+        self.known_keys(package, PACKAGE_KEYS, "[package]");
+        for key in ["name", "version", "path"] {
+            self.string(package, key, "[package]");
+        }
+        if let Some((span, lua)) = self.string(package, "lua", "[package]") {
+            self.version_req(span, &lua);
+        }
+        if let Some((span, target)) = self.string(package, "default-target", "[package]") {
+            if target_lua_version(&target).is_none() {
+                let names: Vec<&str> = TARGETS.iter().map(|(name, _)| *name).collect();
+                self.error(
+                    span,
+                    format!(
+                        "unknown target `{}`, expected one of {}",
+                        target,
+                        names.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    fn features(&mut self, features: &dyn TableLike, dependency_names: &BTreeSet<String>) {
+        for (feature, enables) in features.iter() {
+            let Some(enables) = enables.as_array() else {
+                self.error(
+                    enables.span(),
+                    format!("feature `{}` must be a list of names", feature),
+                );
+                continue;
+            };
+            for enabled in enables.iter() {
+                match enabled.as_str() {
+                    Some(name) if features.contains_key(name) || dependency_names.contains(name) => {}
+                    Some(name) => self.error(
+                        enabled.span(),
+                        format!(
+                            "feature `{}` enables `{}`, which is neither a feature nor a dependency",
+                            feature, name
+                        ),
+                    ),
+                    None => self.error(
+                        enabled.span(),
+                        format!("feature `{}` must be a list of names", feature),
+                    ),
+                }
+            }
+        }
+    }
+
+    fn dependencies(&mut self, deps: &dyn TableLike) {
+        for (name, item) in deps.iter() {
+            self.dependency(&format!("dependency `{}`", name), item);
+        }
+    }
+
+    fn dependency(&mut self, what: &str, item: &Item) {
+        if let Some(url) = item.as_str() {
+            self.url(item.span(), url);
+            return;
+        }
+        let Some(dep) = item.as_table_like() else {
+            self.error(item.span(), format!("{} must be a url or a table", what));
+            return;
+        };
+        self.known_keys(dep, DEPENDENCY_KEYS, what);
+        if let Some(workspace) = dep.get("workspace") {
+            if workspace.as_bool() != Some(true) {
+                self.error(workspace.span(), format!("`workspace` of {} must be true", what));
+            }
+            for (key, _) in dep.iter() {
+                if DEPENDENCY_KEYS.contains(&key) && !INHERITED_KEYS.contains(&key) {
+                    self.error(
+                        dep.key(key).and_then(Key::span),
+                        format!(
+                            "`{}` of {} is set by the workspace, it cannot be used with `workspace`",
+                            key, what
+                        ),
+                    );
+                }
+            }
+            self.enables(dep, what);
+            return;
+        }
+
+        let mut strings = HashMap::new();
+        for key in [
+            "url",
+            "tag",
+            "branch",
+            "hash",
+            "version",
+            "path",
+            "archive",
+            "sha256",
+            "strip-prefix",
+            "package",
+        ] {
+            if let Some(value) = self.string(dep, key, what) {
+                strings.insert(key, value);
+            }
+        }
+        let key_span = |key: &str| dep.key(key).and_then(Key::span);
+
+        // only one way to fetch it, and only one revision of it
+        let sources: Vec<&str> = ["url", "archive", "rock"]
+            .into_iter()
+            .filter(|key| dep.contains_key(key))
+            .collect();
+        if sources.len() > 1 {
+            self.error(
+                key_span(sources[1]),
+                format!(
+                    "`{}` and `{}` of {} cannot be used together",
+                    sources[0], sources[1], what
+                ),
+            );
+        }
+        if sources.is_empty() && !dep.contains_key("path") {
+            self.error(
+                item.span(),
+                format!(
+                    "{} has no source, add `url`, `path`, `archive` or `rock`",
+                    what
+                ),
+            );
+        }
+        if dep.contains_key("path") && (dep.contains_key("archive") || dep.contains_key("rock")) {
+            self.error(
+                key_span("path"),
+                format!("`path` of {} only applies to git and local packages", what),
+            );
+        }
+        let pins: Vec<&str> = ["tag", "branch", "hash"]
+            .into_iter()
+            .filter(|key| dep.contains_key(key))
+            .collect();
+        if pins.len() > 1 {
+            self.error(
+                key_span(pins[1]),
+                format!(
+                    "`{}` and `{}` of {} cannot be used together",
+                    pins[0], pins[1], what
+                ),
+            );
+        }
+        if let Some(pin) = pins.first() {
+            if !dep.contains_key("url") {
+                self.error(
+                    key_span(pin),
+                    format!("`{}` of {} only applies to git dependencies", pin, what),
+                );
+            }
+        }
+
+        if let Some((span, url)) = strings.get("url") {
+            self.url(span.clone(), url);
+        }
+        if let Some((span, version)) = strings.get("version") {
+            self.version_req(span.clone(), version);
+        }
+        if dep.contains_key("archive") && !dep.contains_key("sha256") {
+            self.error(
+                key_span("archive"),
+                format!("archive of {} needs a `sha256`", what),
+            );
+        }
+        if let Some((span, sha256)) = strings.get("sha256") {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                self.error(
+                    span.clone(),
+                    format!("`sha256` of {} must be 64 hex digits", what),
+                );
+            }
+        }
+        if dep.contains_key("strip-prefix") && !dep.contains_key("archive") {
+            self.error(
+                key_span("strip-prefix"),
+                format!("`strip-prefix` of {} only applies to archives", what),
+            );
+        }
+
+        if let Some(rock) = dep.get("rock") {
+            if let Some(table) = rock.as_table_like() {
+                let rock_what = format!("rock of {}", what);
+                self.known_keys(table, ROCK_KEYS, &rock_what);
+                if self.string(table, "name", &rock_what).is_none() {
+                    self.error(rock.span(), format!("{} has no `name`", rock_what));
+                }
+                self.string(table, "version", &rock_what);
+            } else if rock.as_str().is_none() {
+                self.error(
+                    rock.span(),
+                    format!("`rock` of {} must be a name or a table", what),
+                );
+            }
+        }
+        self.enables(dep, what);
+    }
+
+    /// `optional` and `features`, which a member may also set on a dependency
+    /// it inherits.
+    fn enables(&mut self, dep: &dyn TableLike, what: &str) {
+        if let Some(optional) = dep.get("optional") {
+            if optional.as_bool().is_none() {
+                self.error(
+                    optional.span(),
+                    format!("`optional` of {} must be true or false", what),
+                );
+            }
+        }
+        if let Some(features) = dep.get("features") {
+            let names = features
+                .as_array()
+                .is_some_and(|features| features.iter().all(|f| f.as_str().is_some()));
+            if !names {
+                self.error(
+                    features.span(),
+                    format!("`features` of {} must be a list of names", what),
+                );
+            }
+        }
+    }
+
+    fn url(&mut self, span: Option<Range<usize>>, url: &str) {
+        if let Err(e) = parse_repo_url(&expand_host_alias(url, &self.hosts)) {
+            self.error(span, e);
+        }
+    }
+
+    fn version_req(&mut self, span: Option<Range<usize>>, req: &str) {
+        if let Err(e) = parse_version_req(req) {
+            self.error(span, e.message().to_string());
+        }
+    }
+
+    fn known_keys(&mut self, table: &dyn TableLike, known: &[&str], what: &str) {
+        for (key, _) in table.iter() {
+            if known.contains(&key) {
+                continue;
+            }
+            let message = match suggest(key, known) {
+                Some(suggestion) => format!(
+                    "unknown key `{}` in {}, did you mean `{}`?",
+                    key, what, suggestion
+                ),
+                None => format!("unknown key `{}` in {}", key, what),
+            };
+            let span = table.key(key).and_then(Key::span);
+            self.error(span, message);
+        }
+    }
+
+    fn table<'a>(&mut self, parent: &'a dyn TableLike, key: &str) -> Option<&'a dyn TableLike> {
+        let item = parent.get(key)?;
+        match item.as_table_like() {
+            Some(table) => Some(table),
+            None => {
+                self.error(item.span(), format!("`{}` must be a table", key));
+                None
+            }
+        }
+    }
+
+    fn string(
+        &mut self,
+        table: &dyn TableLike,
+        key: &str,
+        what: &str,
+    ) -> Option<(Option<Range<usize>>, String)> {
+        let item = table.get(key)?;
+        match item.as_value().and_then(Value::as_str) {
+            Some(value) => Some((item.span(), value.to_string())),
+            None => {
+                self.error(
+                    item.span(),
+                    format!("`{}` of {} must be a string", key, what),
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The known key closest to a misspelled one, if it is close enough to be a typo.
+fn suggest<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<String> {
+        verify_manifest_str(source)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_valid_manifest() {
+        let source = r#"[package]
+name = "app"
+lua = ">=5.1, <5.5"
+
+[hosts]
+ghe = "https://github.example.com"
+
+[features]
+fast = ["ffi"]
+
+[dependencies]
+json = { url = "rxi/json.lua", version = "^1.2" }
+ffi = { url = "ghe:team/ffi", branch = "main", optional = true }
+mylib = { path = "../mylib" }
+
+[install]
+dir = "vendor/lua"
+layout = "rocks"
+
+[workspace]
+members = ["packages/*", "tools/gen"]
+
+[workspace.dependencies]
+say = { url = "lunarmodules/say", tag = "v1.4.1" }
+
+[dev-dependencies]
+say = { workspace = true, features = ["color"] }
+"#;
+        assert!(messages(source).is_empty());
+    }
+
+    #[test]
+    fn test_verify_lua_requirement() {
+        let source = r#"[package]
+name = "app"
+lua = "5.1 or later"
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["invalid version requirement `5.1 or later`: expected comma after minor version number, found 'o'"]
+        );
+    }
+
+    #[test]
+    fn test_verify_unknown_feature() {
+        let source = r#"[features]
+fast = ["fii"]
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["feature `fast` enables `fii`, which is neither a feature nor a dependency"]
+        );
+    }
+
+    #[test]
+    fn test_verify_unknown_key() {
+        let source = r#"[dependencies]
+json = { url = "rxi/json.lua", brnach = "dev" }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["unknown key `brnach` in dependency `json`, did you mean `branch`?"]
+        );
+    }
+
+    #[test]
+    fn test_verify_tag_and_hash() {
+        let source = r#"[dependencies]
+say = { url = "lunarmodules/say", tag = "v1.4.1", hash = "abc123" }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["`tag` and `hash` of dependency `say` cannot be used together"]
+        );
+    }
+
+    #[test]
+    fn test_verify_unknown_host_alias() {
+        let source = r#"[dependencies]
+ffi = "ghe:team/ffi"
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["unknown host alias `ghe` in `ghe:team/ffi`"]
+        );
+    }
+
+    #[test]
+    fn test_verify_version_requirement() {
+        let source = r#"[dependencies]
+ffi = { url = "team/ffi", version = "one" }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["invalid version requirement `one`: unexpected character 'o' while parsing major version number"]
+        );
+    }
+
+    #[test]
+    fn test_verify_install_dir() {
+        let source = r#"[install]
+dir = "../shared"
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["`dir` of [install] must be a directory inside the project, not `../shared`"]
+        );
+    }
+
+    #[test]
+    fn test_verify_install_layout() {
+        let source = r#"[install]
+layout = "tree"
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["unknown layout `tree`, expected one of nested, flat, rocks"]
+        );
+    }
+
+    #[test]
+    fn test_verify_workspace_member() {
+        let source = r#"[workspace]
+members = ["packages/*", "../other"]
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["workspace member `../other` must be a directory inside the project"]
+        );
+    }
+
+    #[test]
+    fn test_verify_workspace_dependency_inherited() {
+        let source = r#"[workspace.dependencies]
+say = { workspace = true }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["dependency `say` of [workspace.dependencies] cannot be inherited from the workspace itself"]
+        );
+    }
+
+    #[test]
+    fn test_verify_inherited_must_be_true() {
+        let source = r#"[dependencies]
+say = { workspace = false }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["`workspace` of dependency `say` must be true"]
+        );
+    }
+
+    #[test]
+    fn test_verify_inherited_source_key() {
+        let source = r#"[dev-dependencies]
+busted = { workspace = true, tag = "v2" }
+"#;
+        assert_eq!(
+            messages(source),
+            vec!["`tag` of dependency `busted` is set by the workspace, it cannot be used with `workspace`"]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "[dependencies]\njson = { url = \"rxi/json.lua\", brnach = \"dev\" }\n";
+        let diagnostics = verify_manifest_str(source);
+        assert_eq!(
+            render("package.toml", source, &diagnostics[0]),
+            r#"error: unknown key `brnach` in dependency `json`, did you mean `branch`?
+ --> package.toml:2:32
+  |
+2 | json = { url = "rxi/json.lua", brnach = "dev" }
+  |                                ^^^^^^"#
+        );
+
+        let diagnostics = verify_manifest_str("[dependencies\njson = 1\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            render("package.toml", "[dependencies\njson = 1\n", &diagnostics[0])
+                .contains("package.toml:1:14")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use lua_workspace_config::workspace_config::WorkspaceConfig;
+
+/// A package of the workspace, installed in place like a path dependency.
+#[derive(Debug)]
+pub(crate) struct Member {
+    pub name: String,
+    /// Relative to the root of the workspace.
+    pub path: String,
+}
+
+/// The members of the `[workspace]` of the root manifest. A pattern with a
+/// `*` matches every directory with a package.toml, any other names one.
+pub(crate) fn workspace_members(
+    base_path: &Path,
+    config: &WorkspaceConfig,
+) -> Result<Vec<Member>, String> {
+    let patterns = config
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.members.clone())
+        .unwrap_or_default();
+
+    let mut members: BTreeMap<String, String> = BTreeMap::new();
+    for pattern in &patterns {
+        let parts: Vec<&str> = pattern
+            .split(['/', '\\'])
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        let mut dirs = Vec::new();
+        expand_pattern(base_path, &parts, Vec::new(), &mut dirs);
+        if !pattern.contains('*') && dirs.is_empty() {
+            return Err(format!(
+                "workspace member `{}` has no package.toml",
+                pattern
+            ));
+        }
+        for dir in dirs {
+            let path = dir.join("/");
+            let manifest = base_path.join(&path).join("package.toml");
+            let config = WorkspaceConfig::parse_toml_file(manifest.to_str().unwrap())
+                .map_err(|e| format!("Failed to parse {}: {}", manifest.display(), e))?;
+            let name = config
+                .package
+                .and_then(|package| package.name)
+                .unwrap_or_else(|| dir.last().cloned().unwrap_or_default());
+            match members.get(&name) {
+                Some(other) if *other != path => {
+                    return Err(format!(
+                        "workspace members {} and {} are both named `{}`",
+                        other, path, name
+                    ))
+                }
+                _ => {
+                    members.insert(name, path);
+                }
+            }
+        }
+    }
+    Ok(members
+        .into_iter()
+        .map(|(name, path)| Member { name, path })
+        .collect())
+}
+
+fn expand_pattern(base_path: &Path, parts: &[&str], dir: Vec<String>, dirs: &mut Vec<Vec<String>>) {
+    let Some((part, rest)) = parts.split_first() else {
+        if base_path.join(dir.join("/")).join("package.toml").is_file() {
+            dirs.push(dir);
+        }
+        return;
+    };
+    if !part.contains('*') {
+        let mut dir = dir;
+        dir.push(part.to_string());
+        expand_pattern(base_path, rest, dir, dirs);
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(base_path.join(dir.join("/"))) else {
+        return;
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') && wildcard_match(part, name))
+        .collect();
+    names.sort();
+    for name in names {
+        let mut dir = dir.clone();
+        dir.push(name);
+        expand_pattern(base_path, rest, dir, dirs);
+    }
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| wildcard_match(rest, &name[i..]))
+        }),
+    }
+}
+
+/// The root of the workspace `package_root` is a member of, if any.
+pub(crate) fn find_workspace_root(package_root: &Path) -> Option<PathBuf> {
+    package_root.ancestors().skip(1).find_map(|dir| {
+        let manifest = dir.join("package.toml");
+        if !manifest.is_file() {
+            return None;
+        }
+        let config = WorkspaceConfig::parse_toml_file(manifest.to_str()?).ok()?;
+        workspace_members(dir, &config)
+            .ok()?
+            .iter()
+            .any(|member| dir.join(&member.path) == package_root)
+            .then(|| dir.to_path_buf())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_workspace_members() {
+        let dir = std::env::temp_dir().join("luap_workspace_members");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        for (path, manifest) in [
+            ("packages/core", "[package]\nname = \"lib-core\"\n"),
+            ("packages/app", ""),
+            ("packages/docs", ""),
+            ("tools/gen", ""),
+        ] {
+            fs::create_dir_all(dir.join(path)).unwrap();
+            fs::write(dir.join(path).join("package.toml"), manifest).unwrap();
+        }
+        fs::remove_file(dir.join("packages/docs/package.toml")).unwrap();
+        let manifest = "[workspace]\nmembers = [\"packages/*\", \"./tools/gen\"]\n";
+        fs::write(dir.join("package.toml"), manifest).unwrap();
+
+        let config = WorkspaceConfig::parse_toml_str(manifest).unwrap();
+        let members = workspace_members(&dir, &config).unwrap();
+        let names: Vec<(&str, &str)> = members
+            .iter()
+            .map(|member| (member.name.as_str(), member.path.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("app", "packages/app"),
+                ("gen", "tools/gen"),
+                ("lib-core", "packages/core"),
+            ]
+        );
+        assert_eq!(
+            find_workspace_root(&dir.join("packages/app")),
+            Some(dir.clone())
+        );
+        assert_eq!(find_workspace_root(&dir.join("packages/docs")), None);
+
+        let config =
+            WorkspaceConfig::parse_toml_str("[workspace]\nmembers = [\"packages/docs\"]\n")
+                .unwrap();
+        assert_eq!(
+            workspace_members(&dir, &config).unwrap_err(),
+            "workspace member `packages/docs` has no package.toml"
+        );
+    }
+}
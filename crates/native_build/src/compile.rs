@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use github_package::luap_home;
+use lua_workspace_config::workspace_config::{BuildConfig, ModuleBuild};
+
+#[derive(Debug)]
+pub struct BuildOutput {
+    /// Names of the modules that were built, sorted.
+    pub modules: Vec<String>,
+    /// Whether they were copied from an earlier build of the same source.
+    pub cached: bool,
+}
+
+/// Builds are kept under `$LUAP_HOME/cache/build/<target>`, one directory for
+/// every package and the source revision it was built from.
+pub fn cache_build_path(target: &str, name: &str, key: &str) -> PathBuf {
+    luap_home()
+        .join("cache")
+        .join("build")
+        .join(target)
+        .join(format!("{}-{}", name, key))
+}
+
+/// Where a module is found by `require`, `socket.core` is `socket/core.so`.
+pub fn module_file(module: &str) -> PathBuf {
+    let extension = if cfg!(windows) { "dll" } else { "so" };
+    let mut path: PathBuf = module.split('.').collect();
+    path.set_extension(extension);
+    path
+}
+
+/// Builds the C modules of the package in `package_dir` into `out_dir`. A
+/// package built from a known revision is built once into `cache` and copied
+/// from there afterwards, without a cache it is built every time.
+pub fn build_modules(
+    package_dir: &Path,
+    build: &BuildConfig,
+    lua_incdir: &Path,
+    cache: Option<&Path>,
+    out_dir: &Path,
+) -> Result<BuildOutput, String> {
+    let mut modules: Vec<(&String, &ModuleBuild)> = build.modules.iter().flatten().collect();
+    modules.sort_by_key(|(name, _)| *name);
+    let names: Vec<String> = modules.iter().map(|(name, _)| name.to_string()).collect();
+
+    let Some(cache) = cache else {
+        for (name, module) in &modules {
+            compile_module(package_dir, name, module, lua_incdir, out_dir)?;
+        }
+        return Ok(BuildOutput {
+            modules: names,
+            cached: false,
+        });
+    };
+
+    let cached = cache.is_dir();
+    if !cached {
+        // a build that fails half way is never found in the cache
+        let file_name = cache.file_name().unwrap().to_string_lossy();
+        let tmp_dir = cache.with_file_name(format!("{}.tmp", file_name));
+        if tmp_dir.exists() {
+            std::fs::remove_dir_all(&tmp_dir).map_err(|e| e.to_string())?;
+        }
+        for (name, module) in &modules {
+            compile_module(package_dir, name, module, lua_incdir, &tmp_dir)?;
+        }
+        std::fs::create_dir_all(&tmp_dir).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp_dir, cache).map_err(|e| e.to_string())?;
+    }
+    for name in &names {
+        let file = module_file(name);
+        let to_path = out_dir.join(&file);
+        if let Some(parent) = to_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::copy(cache.join(&file), &to_path)
+            .map_err(|e| format!("failed to copy {}: {}", to_path.display(), e))?;
+    }
+    Ok(BuildOutput {
+        modules: names,
+        cached,
+    })
+}
+
+/// Compiles and links one module with `$CC`, or `cc`, in a single step.
+fn compile_module(
+    package_dir: &Path,
+    name: &str,
+    module: &ModuleBuild,
+    lua_incdir: &Path,
+    out_dir: &Path,
+) -> Result<(), String> {
+    // the compiler runs in the package directory
+    let out_path =
+        std::path::absolute(out_dir.join(module_file(name))).map_err(|e| e.to_string())?;
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let compiler = std::env::var("CC")
+        .ok()
+        .filter(|cc| !cc.is_empty())
+        .unwrap_or_else(|| "cc".to_string());
+    let mut command = Command::new(&compiler);
+    command.current_dir(package_dir);
+    if cfg!(target_os = "macos") {
+        // the symbols of Lua come from the interpreter that loads the module
+        command.args(["-bundle", "-undefined", "dynamic_lookup"]);
+    } else {
+        command.arg("-shared");
+    }
+    command.args(["-O2", "-fPIC"]);
+    if let Ok(cflags) = std::env::var("CFLAGS") {
+        command.args(cflags.split_whitespace());
+    }
+    command.arg(format!("-I{}", lua_incdir.display()));
+    for dir in module.incdirs.iter().flatten() {
+        command.arg(format!("-I{}", dir));
+    }
+    for define in module.defines.iter().flatten() {
+        command.arg(format!("-D{}", define));
+    }
+    command.args(&module.sources);
+    for dir in module.libdirs.iter().flatten() {
+        command.arg(format!("-L{}", dir));
+    }
+    for library in module.libraries.iter().flatten() {
+        command.arg(format!("-l{}", library));
+    }
+    command.arg("-o").arg(&out_path);
+
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", compiler, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to build {}: {} {}\n{}",
+            name,
+            compiler,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_build_modules_from_cache() {
+        assert_eq!(
+            module_file("socket.core"),
+            Path::new("socket").join(format!("core.{}", if cfg!(windows) { "dll" } else { "so" }))
+        );
+
+        let dir = std::env::temp_dir().join("native_build_from_cache");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        let cache = dir.join("cache");
+        std::fs::create_dir_all(cache.join("socket")).unwrap();
+        std::fs::write(cache.join(module_file("socket.core")), "built").unwrap();
+
+        // sources that do not exist would fail to compile, a cached build is
+        // only copied
+        let module = ModuleBuild {
+            sources: vec!["missing.c".to_string()],
+            incdirs: None,
+            defines: None,
+            libraries: None,
+            libdirs: None,
+        };
+        let build = BuildConfig {
+            modules: Some(HashMap::from([("socket.core".to_string(), module)])),
+        };
+        let out_dir = dir.join("out");
+        let output = build_modules(&dir, &build, &dir, Some(&cache), &out_dir).unwrap();
+        assert!(output.cached);
+        assert_eq!(output.modules, vec!["socket.core"]);
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join(module_file("socket.core"))).unwrap(),
+            "built"
+        );
+        assert!(build_modules(&dir, &build, &dir, None, &out_dir).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use lua_workspace_config::workspace_config::{target_lua_version, TARGETS};
+
+const INCLUDE_DIRS: &[&str] = &[
+    "/usr/include",
+    "/usr/local/include",
+    "/opt/homebrew/include",
+];
+
+/// Finds the directory with `lua.h` for a target: `$LUA_INCDIR`, then the
+/// places distributions and Homebrew install the headers to. Without a
+/// target any Lua version will do, the newest first.
+pub fn find_lua_incdir(target: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(dir) = std::env::var_os("LUA_INCDIR").filter(|dir| !dir.is_empty()) {
+        let dir = PathBuf::from(dir);
+        if !has_lua_h(&dir) {
+            return Err(format!("LUA_INCDIR {} has no lua.h", dir.display()));
+        }
+        return Ok(dir);
+    }
+
+    let targets: Vec<&str> = match target {
+        Some(target) => vec![target],
+        None => TARGETS.iter().rev().map(|(name, _)| *name).collect(),
+    };
+    for target in &targets {
+        for dir in INCLUDE_DIRS {
+            for subdir in header_subdirs(target) {
+                let dir = Path::new(dir).join(subdir);
+                if has_lua_h(&dir) {
+                    return Ok(dir);
+                }
+            }
+        }
+    }
+    // headers installed straight into the include directory are of whatever
+    // version that is, only good enough when no target was asked for
+    if target.is_none() {
+        for dir in INCLUDE_DIRS {
+            if has_lua_h(Path::new(dir)) {
+                return Ok(PathBuf::from(dir));
+            }
+        }
+    }
+
+    Err(format!(
+        "Lua headers{} not found, install them or set LUA_INCDIR",
+        target.map(|t| format!(" for {}", t)).unwrap_or_default()
+    ))
+}
+
+fn header_subdirs(target: &str) -> Vec<String> {
+    if target == "luajit" {
+        return vec!["luajit-2.1".to_string(), "luajit-2.0".to_string()];
+    }
+    let Some(version) = target_lua_version(target) else {
+        return Vec::new();
+    };
+    vec![
+        format!("lua{}", version),
+        format!("lua{}", version.replace('.', "")),
+        format!("lua-{}", version),
+    ]
+}
+
+fn has_lua_h(dir: &Path) -> bool {
+    dir.join("lua.h").is_file()
+}
@@ -0,0 +1,5 @@
+pub mod compile;
+pub mod headers;
+
+pub use compile::{build_modules, cache_build_path, module_file, BuildOutput};
+pub use headers::find_lua_incdir;
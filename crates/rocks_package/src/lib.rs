@@ -0,0 +1,11 @@
+pub mod lua_table;
+pub mod manifest;
+pub mod rock;
+pub mod rockspec;
+
+pub use manifest::{parse_manifest, RockManifest, RockRelease};
+pub use rock::{
+    fetch_rock, install_rock, modified_files, open_cached_rock, read_rock, read_server_manifest,
+    rock_checksum, rocks_server, RockContents,
+};
+pub use rockspec::{parse_rockspec, Rockspec};
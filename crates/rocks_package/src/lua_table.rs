@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+
+/// The subset of Lua values that LuaRocks manifests and rockspecs are made of.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(LuaTable),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LuaTable {
+    /// Positional entries, `{ "a", "b" }`.
+    pub array: Vec<LuaValue>,
+    /// Keyed entries, `{ a = 1, ["b-c"] = 2 }`.
+    pub fields: BTreeMap<String, LuaValue>,
+}
+
+impl LuaValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            LuaValue::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.as_table()?.fields.get(key)
+    }
+}
+
+/// Evaluates a file of global assignments, which is what a rockspec or a
+/// manifest is. `local` variables and string concatenation are understood,
+/// function calls and control flow are not.
+pub fn parse_assignments(source: &str) -> Result<BTreeMap<String, LuaValue>, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        globals: BTreeMap::new(),
+        locals: BTreeMap::new(),
+    };
+    parser.parse_chunk()?;
+    Ok(parser.globals)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    globals: BTreeMap<String, LuaValue>,
+    locals: BTreeMap<String, LuaValue>,
+}
+
+impl Parser {
+    fn error<T>(&self, message: impl std::fmt::Display) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    fn parse_chunk(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_space()?;
+            if self.peek().is_none() {
+                return Ok(());
+            }
+            if self.eat(';') {
+                continue;
+            }
+
+            let mut name = self.parse_name()?;
+            let local = name == "local";
+            if local {
+                self.skip_space()?;
+                name = self.parse_name()?;
+            }
+            self.skip_space()?;
+            if !self.eat('=') {
+                return self.error(format!("expected `=` after `{}`", name));
+            }
+            let value = self.parse_expression()?;
+            if local {
+                self.locals.insert(name, value);
+            } else {
+                self.globals.insert(name, value);
+            }
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<LuaValue, String> {
+        let mut value = self.parse_value()?;
+        loop {
+            self.skip_space()?;
+            if !self.starts_with("..") {
+                return Ok(value);
+            }
+            self.pos += 2;
+            let right = self.parse_value()?;
+            value = match (concat_part(&value), concat_part(&right)) {
+                (Some(left), Some(right)) => LuaValue::String(left + &right),
+                _ => return self.error("only strings and numbers can be concatenated"),
+            };
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<LuaValue, String> {
+        self.skip_space()?;
+        match self.peek() {
+            Some('{') => self.parse_table(),
+            Some('"') | Some('\'') => self.parse_string().map(LuaValue::String),
+            Some('[') if matches!(self.peek_at(1), Some('[') | Some('=')) => {
+                self.parse_long_string().map(LuaValue::String)
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => self.parse_number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.parse_name()?;
+                match name.as_str() {
+                    "nil" => Ok(LuaValue::Nil),
+                    "true" => Ok(LuaValue::Bool(true)),
+                    "false" => Ok(LuaValue::Bool(false)),
+                    _ => Ok(self
+                        .locals
+                        .get(&name)
+                        .or_else(|| self.globals.get(&name))
+                        .cloned()
+                        .unwrap_or(LuaValue::Nil)),
+                }
+            }
+            Some(c) => self.error(format!("unexpected `{}`", c)),
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn parse_table(&mut self) -> Result<LuaValue, String> {
+        self.pos += 1;
+        let mut table = LuaTable::default();
+        loop {
+            self.skip_space()?;
+            if self.eat('}') {
+                return Ok(LuaValue::Table(table));
+            }
+
+            if self.peek() == Some('[') && !matches!(self.peek_at(1), Some('[') | Some('=')) {
+                self.pos += 1;
+                let key = match self.parse_expression()? {
+                    LuaValue::String(key) => key,
+                    LuaValue::Number(key) => key.to_string(),
+                    _ => return self.error("table keys must be strings or numbers"),
+                };
+                self.skip_space()?;
+                if !self.eat(']') {
+                    return self.error("expected `]`");
+                }
+                self.expect_assign()?;
+                let value = self.parse_expression()?;
+                table.fields.insert(key, value);
+            } else if self.is_field_name() {
+                let key = self.parse_name()?;
+                self.expect_assign()?;
+                let value = self.parse_expression()?;
+                table.fields.insert(key, value);
+            } else {
+                let value = self.parse_expression()?;
+                table.array.push(value);
+            }
+
+            self.skip_space()?;
+            if !self.eat(',') && !self.eat(';') {
+                self.skip_space()?;
+                if !self.eat('}') {
+                    return self.error("expected `,` or `}` in table");
+                }
+                return Ok(LuaValue::Table(table));
+            }
+        }
+    }
+
+    // `name =` starts a field, a bare `name` is a value
+    fn is_field_name(&self) -> bool {
+        let mut pos = self.pos;
+        if !self
+            .chars
+            .get(pos)
+            .is_some_and(|c| c.is_alphabetic() || *c == '_')
+        {
+            return false;
+        }
+        while self
+            .chars
+            .get(pos)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        {
+            pos += 1;
+        }
+        while self.chars.get(pos).is_some_and(|c| c.is_whitespace()) {
+            pos += 1;
+        }
+        self.chars.get(pos) == Some(&'=') && self.chars.get(pos + 1) != Some(&'=')
+    }
+
+    fn expect_assign(&mut self) -> Result<(), String> {
+        self.skip_space()?;
+        if !self.eat('=') {
+            return self.error("expected `=`");
+        }
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return match self.peek() {
+                Some(c) => self.error(format!("unexpected `{}`", c)),
+                None => self.error("unexpected end of file"),
+            };
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn parse_number(&mut self) -> Result<LuaValue, String> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '.')
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        match text.parse() {
+            Ok(number) => Ok(LuaValue::Number(number)),
+            Err(_) => self.error(format!("invalid number `{}`", text)),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        let quote = self.chars[self.pos];
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => return Ok(value),
+                '\n' => return self.error("unterminated string"),
+                '\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return self.error("unterminated string");
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        '\n' => {
+                            self.line += 1;
+                            value.push('\n');
+                        }
+                        c if c.is_ascii_digit() => {
+                            let mut code = c.to_digit(10).unwrap();
+                            for _ in 0..2 {
+                                match self.peek().and_then(|c| c.to_digit(10)) {
+                                    Some(digit) => {
+                                        code = code * 10 + digit;
+                                        self.pos += 1;
+                                    }
+                                    None => break,
+                                }
+                            }
+                            value.push(char::from_u32(code).unwrap_or('?'));
+                        }
+                        c => value.push(c),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_long_string(&mut self) -> Result<String, String> {
+        let Some(level) = self.long_bracket_level() else {
+            return self.error("invalid long string");
+        };
+        self.pos += level + 2;
+        let close: String = format!("]{}]", "=".repeat(level));
+        // a newline right after the opening bracket is not part of the string
+        if self.peek() == Some('\n') {
+            self.pos += 1;
+            self.line += 1;
+        }
+        let start = self.pos;
+        while !self.starts_with(&close) {
+            match self.peek() {
+                Some('\n') => self.line += 1,
+                Some(_) => {}
+                None => return self.error("unterminated long string"),
+            }
+            self.pos += 1;
+        }
+        let value = self.chars[start..self.pos].iter().collect();
+        self.pos += close.len();
+        Ok(value)
+    }
+
+    // `[[` is level 0, `[==[` is level 2
+    fn long_bracket_level(&self) -> Option<usize> {
+        if self.peek() != Some('[') {
+            return None;
+        }
+        let mut level = 0;
+        while self.peek_at(level + 1) == Some('=') {
+            level += 1;
+        }
+        (self.peek_at(level + 1) == Some('[')).then_some(level)
+    }
+
+    fn skip_space(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some('\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some(c) if c.is_whitespace() => self.pos += 1,
+                Some('-') if self.peek_at(1) == Some('-') => {
+                    self.pos += 2;
+                    if self.long_bracket_level().is_some() {
+                        self.parse_long_string()?;
+                    } else {
+                        while self.peek().is_some_and(|c| c != '\n') {
+                            self.pos += 1;
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn concat_part(value: &LuaValue) -> Option<String> {
+    match value {
+        LuaValue::String(s) => Some(s.clone()),
+        LuaValue::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rockspec_like_source() {
+        let source = r#"
+-- a comment
+local v = "1.1.0"
+package = "lpeg"
+version = v .. "-1"
+source = {
+   url = "https://www.inf.puc-rio.br/~roberto/lpeg/lpeg-" .. v .. ".tar.gz",
+   md5 = 'abc\'d',
+}
+description = {
+   detailed = [[
+Long text with "quotes".]],
+}
+--[[ a long
+comment ]]
+dependencies = { "lua >= 5.1", }
+build = {
+   type = "builtin",
+   modules = {
+      lpeg = { "lpcap.c", "lpcode.c" },
+      ["re"] = "re.lua";
+   },
+   debug = false, level = -2,
+}
+"#;
+        let globals = parse_assignments(source).unwrap();
+        assert_eq!(globals["version"].as_str(), Some("1.1.0-1"));
+        assert!(!globals.contains_key("v"));
+        assert_eq!(
+            globals["source"].get("url").and_then(LuaValue::as_str),
+            Some("https://www.inf.puc-rio.br/~roberto/lpeg/lpeg-1.1.0.tar.gz")
+        );
+        assert_eq!(
+            globals["source"].get("md5").and_then(LuaValue::as_str),
+            Some("abc'd")
+        );
+        assert_eq!(
+            globals["description"]
+                .get("detailed")
+                .and_then(LuaValue::as_str),
+            Some("Long text with \"quotes\".")
+        );
+        let dependencies = globals["dependencies"].as_table().unwrap();
+        assert_eq!(
+            dependencies.array,
+            vec![LuaValue::String("lua >= 5.1".to_string())]
+        );
+        let build = &globals["build"];
+        assert_eq!(
+            build.get("modules").and_then(|m| m.get("re")),
+            Some(&LuaValue::String("re.lua".to_string()))
+        );
+        assert_eq!(build.get("level"), Some(&LuaValue::Number(-2.0)));
+        assert_eq!(build.get("debug"), Some(&LuaValue::Bool(false)));
+    }
+
+    #[test]
+    fn test_parse_errors_have_line_numbers() {
+        let err = parse_assignments("package = \"lpeg\"\nversion = {\n").unwrap_err();
+        assert_eq!(err, "line 3: unexpected end of file");
+        assert!(parse_assignments("print(\"hi\")").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use github_package::tag::parse_tag_version;
+use semver::{Version, VersionReq};
+
+use crate::lua_table::{parse_assignments, LuaValue};
+
+/// The `manifest` file at the root of a rocks server: every version of every
+/// rock, and the forms each one is published in.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RockManifest {
+    pub repository: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+/// One version of a rock that luap can install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RockRelease {
+    pub name: String,
+    pub version: String,
+    /// `src` for a `.src.rock`, `rockspec` when only the rockspec is published.
+    pub arch: String,
+}
+
+impl RockRelease {
+    pub fn file_name(&self) -> String {
+        if self.arch == "rockspec" {
+            format!("{}-{}.rockspec", self.name, self.version)
+        } else {
+            format!("{}-{}.{}.rock", self.name, self.version, self.arch)
+        }
+    }
+}
+
+pub fn parse_manifest(source: &str) -> Result<RockManifest, String> {
+    let globals = parse_assignments(source)?;
+    let mut manifest = RockManifest::default();
+    let Some(repository) = globals.get("repository").and_then(LuaValue::as_table) else {
+        return Ok(manifest);
+    };
+
+    for (name, versions) in &repository.fields {
+        let Some(versions) = versions.as_table() else {
+            continue;
+        };
+        let versions = versions
+            .fields
+            .iter()
+            .map(|(version, entries)| {
+                let arches = entries
+                    .as_table()
+                    .map(|entries| {
+                        entries
+                            .array
+                            .iter()
+                            .filter_map(|entry| entry.get("arch")?.as_str())
+                            .map(|arch| arch.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (version.clone(), arches)
+            })
+            .collect();
+        manifest.repository.insert(name.clone(), versions);
+    }
+    Ok(manifest)
+}
+
+impl RockManifest {
+    /// Picks the highest version of `name` that has a source form and matches
+    /// both `version`, a LuaRocks version with or without its revision, and
+    /// `req`. Development versions such as `scm-1` are only picked by name.
+    pub fn find_release(
+        &self,
+        name: &str,
+        version: Option<&str>,
+        req: Option<&VersionReq>,
+    ) -> Option<RockRelease> {
+        self.repository
+            .get(name)?
+            .iter()
+            .filter(|(candidate, _)| match version {
+                Some(version) => *candidate == version || strip_revision(candidate) == version,
+                None => rock_version_key(candidate).is_some(),
+            })
+            .filter(|(candidate, _)| match req {
+                Some(req) => rock_semver(candidate).is_some_and(|v| req.matches(&v)),
+                None => true,
+            })
+            .filter_map(|(candidate, arches)| {
+                let arch = ["src", "rockspec"]
+                    .into_iter()
+                    .find(|arch| arches.iter().any(|a| a == arch))?;
+                Some(RockRelease {
+                    name: name.to_string(),
+                    version: candidate.clone(),
+                    arch: arch.to_string(),
+                })
+            })
+            .max_by(|a, b| compare_rock_versions(&a.version, &b.version))
+    }
+}
+
+/// A rock version without its rockspec revision, `1.1.0-1` is `1.1.0`.
+pub fn strip_revision(version: &str) -> &str {
+    version
+        .rsplit_once('-')
+        .map_or(version, |(version, _)| version)
+}
+
+/// Reads a rock version as semver, so that `version = "^1.1"` works for rocks
+/// the same way it does for git tags.
+pub fn rock_semver(version: &str) -> Option<Version> {
+    parse_tag_version(strip_revision(version))
+}
+
+fn rock_version_key(version: &str) -> Option<(Vec<u64>, u64)> {
+    let (version, revision) = match version.rsplit_once('-') {
+        Some((version, revision)) => (version, revision.parse().ok()?),
+        None => (version, 0),
+    };
+    let parts = version
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some((parts, revision))
+}
+
+fn compare_rock_versions(a: &str, b: &str) -> Ordering {
+    match (rock_version_key(a), rock_version_key(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+commands = {}
+modules = {}
+repository = {
+   lpeg = {
+      ["1.0.2-1"] = {
+         { arch = "rockspec" }, { arch = "src" }
+      },
+      ["1.1.0-1"] = {
+         { arch = "rockspec" }, { arch = "src" }
+      },
+      ["1.1.0-2"] = {
+         { arch = "rockspec" }
+      },
+      ["scm-1"] = {
+         { arch = "rockspec" }
+      },
+   },
+   ["lua-cjson"] = {
+      ["2.1.0-1"] = {
+         { arch = "linux-x86_64" }
+      }
+   }
+}
+"#;
+
+    #[test]
+    fn test_find_release() {
+        let manifest = parse_manifest(MANIFEST).unwrap();
+        let find = |version: Option<&str>, req: Option<&str>| {
+            let req = req.map(|req| VersionReq::parse(req).unwrap());
+            manifest
+                .find_release("lpeg", version, req.as_ref())
+                .map(|release| release.file_name())
+        };
+
+        assert_eq!(find(None, None), Some("lpeg-1.1.0-2.rockspec".to_string()));
+        assert_eq!(
+            find(Some("1.1.0-1"), None),
+            Some("lpeg-1.1.0-1.src.rock".to_string())
+        );
+        assert_eq!(
+            find(Some("1.0.2"), None),
+            Some("lpeg-1.0.2-1.src.rock".to_string())
+        );
+        assert_eq!(
+            find(None, Some("~1.0")),
+            Some("lpeg-1.0.2-1.src.rock".to_string())
+        );
+        assert_eq!(
+            find(Some("scm-1"), None),
+            Some("lpeg-scm-1.rockspec".to_string())
+        );
+        assert_eq!(find(None, Some("^2")), None);
+
+        // binary rocks only are of no use
+        assert_eq!(manifest.find_release("lua-cjson", None, None), None);
+    }
+}
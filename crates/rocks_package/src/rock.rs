@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use archive_package::cache::write_atomic;
+use archive_package::{read_files, read_url};
+use github_package::luap_home;
+use sha2::{Digest, Sha256};
+
+use crate::manifest::{parse_manifest, RockManifest};
+use crate::rockspec::{module_path, parse_rockspec, Rockspec};
+
+const DEFAULT_SERVER: &str = "https://luarocks.org";
+
+/// The rocks server to use: `$LUAP_ROCKS_SERVER`, then the `[rocks] server`
+/// of package.toml, then luarocks.org. A server is a url or a local directory
+/// laid out like one.
+pub fn rocks_server(server: Option<&str>) -> String {
+    let server = std::env::var("LUAP_ROCKS_SERVER")
+        .ok()
+        .filter(|server| !server.is_empty())
+        .or_else(|| server.map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    server.trim_end_matches('/').to_string()
+}
+
+pub fn read_server_manifest(server: &str) -> Result<RockManifest, String> {
+    let url = format!("{}/manifest", server);
+    let manifest = read_url(&url)?;
+    parse_manifest(&String::from_utf8_lossy(&manifest))
+        .map_err(|e| format!("failed to parse {}: {}", url, e))
+}
+
+/// Rocks and their sources are kept under `$LUAP_HOME/cache/rocks`.
+pub fn cache_rock_path(url: &str) -> PathBuf {
+    let file_name: String = url
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    luap_home().join("cache").join("rocks").join(file_name)
+}
+
+/// Opens the cached copy of a `.rock` or `.rockspec` without downloading it.
+pub fn open_cached_rock(url: &str) -> Result<PathBuf, String> {
+    let path = cache_rock_path(url);
+    if !path.exists() {
+        return Err(format!(
+            "{} is not in the luap cache, run `luap install`",
+            url
+        ));
+    }
+    Ok(path)
+}
+
+pub fn fetch_rock(url: &str) -> Result<PathBuf, String> {
+    if let Ok(path) = open_cached_rock(url) {
+        return Ok(path);
+    }
+    let content = read_url(url)?;
+    let path = cache_rock_path(url);
+    write_atomic(&path, &content).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+pub fn rock_checksum(path: &Path) -> Result<String, String> {
+    let content = std::fs::read(path).map_err(|e| e.to_string())?;
+    Ok(format!("sha256:{:x}", Sha256::digest(content)))
+}
+
+/// What installing a rock writes: its pure Lua modules laid out by module
+/// name, relative to `lua_modules/<name>`.
+#[derive(Debug)]
+pub struct RockContents {
+    pub rockspec: Rockspec,
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Reads a cached `.src.rock`, or a `.rockspec` together with the source
+/// archive it points at.
+pub fn read_rock(path: &Path, url: &str) -> Result<RockContents, String> {
+    let (rockspec, tree) = if url.ends_with(".rockspec") {
+        let rockspec = std::fs::read(path).map_err(|e| e.to_string())?;
+        let rockspec = parse_rockspec(&String::from_utf8_lossy(&rockspec))?;
+        let source_url = rockspec
+            .source_url
+            .clone()
+            .ok_or_else(|| format!("{} has no source url", url))?;
+        if !["https://", "http://", "file://"]
+            .iter()
+            .any(|scheme| source_url.starts_with(scheme))
+        {
+            return Err(format!(
+                "{} is fetched from {}, add it as a git dependency instead",
+                rockspec.package, source_url
+            ));
+        }
+        let source = fetch_rock(&source_url)?;
+        let tree = read_files(&source, None).map_err(|e| e.to_string())?;
+        (rockspec, tree)
+    } else {
+        let mut files = read_files(path, None).map_err(|e| e.to_string())?;
+        let pos = files
+            .iter()
+            .position(|(name, _)| !name.contains('/') && name.ends_with(".rockspec"))
+            .ok_or_else(|| format!("{} has no rockspec", url))?;
+        let (_, rockspec) = files.remove(pos);
+        let rockspec = parse_rockspec(&String::from_utf8_lossy(&rockspec))?;
+
+        // the source archive is packed as it was downloaded, or as a directory
+        let archive_name = rockspec
+            .source_url
+            .as_ref()
+            .and_then(|url| url.rsplit('/').next())
+            .map(str::to_string);
+        let archive = files
+            .iter()
+            .find(|(name, _)| Some(name) == archive_name.as_ref())
+            .map(|(_, content)| content.clone());
+        let tree = match archive {
+            Some(archive) => {
+                let source = cache_rock_path(&format!("{}.source", url));
+                if !source.exists() {
+                    write_atomic(&source, &archive).map_err(|e| e.to_string())?;
+                }
+                read_files(&source, None).map_err(|e| e.to_string())?
+            }
+            None => files
+                .into_iter()
+                .filter(|(name, _)| name != "rock_manifest")
+                .collect(),
+        };
+        (rockspec, tree)
+    };
+
+    let tree = strip_source_dir(tree, rockspec.source_dir.as_deref());
+    let mut files = BTreeMap::new();
+    for (module, file) in &rockspec.lua_modules {
+        let content = tree
+            .get(file.trim_start_matches("./"))
+            .ok_or_else(|| format!("{} of module {} is not in {}", file, module, url))?;
+        files.insert(module_path(module, file), content.clone());
+    }
+    Ok(RockContents { rockspec, files })
+}
+
+/// Writes the modules of a rock to `to_path`, replacing whatever was there.
+pub fn install_rock(path: &Path, url: &str, to_path: &Path) -> Result<RockContents, String> {
+    let contents = read_rock(path, url)?;
+    let write = || -> Result<(), std::io::Error> {
+        if to_path.exists() {
+            std::fs::remove_dir_all(to_path)?;
+        }
+        std::fs::create_dir_all(to_path)?;
+        for (file, content) in &contents.files {
+            let file = to_path.join(file);
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(file, content)?;
+        }
+        Ok(())
+    };
+    write().map_err(|e| e.to_string())?;
+    Ok(contents)
+}
+
+/// Modules of a rock that are missing from `dir` or differ from it.
+pub fn modified_files(path: &Path, url: &str, dir: &Path) -> Result<Vec<String>, String> {
+    let contents = read_rock(path, url)?;
+    Ok(contents
+        .files
+        .into_iter()
+        .filter(|(file, content)| std::fs::read(dir.join(file)).ok().as_ref() != Some(content))
+        .map(|(file, _)| file)
+        .collect())
+}
+
+// files are named relative to `source.dir`, which defaults to the single top
+// directory most source archives have
+fn strip_source_dir(
+    tree: Vec<(String, Vec<u8>)>,
+    source_dir: Option<&str>,
+) -> BTreeMap<String, Vec<u8>> {
+    let top_dir = match source_dir {
+        Some(dir) => Some(dir.trim_matches('/').to_string()),
+        None => {
+            let mut tops = tree
+                .iter()
+                .map(|(name, _)| name.split_once('/').map(|(top, _)| top));
+            match tops.next().flatten() {
+                Some(first) if tops.all(|top| top == Some(first)) => Some(first.to_string()),
+                _ => None,
+            }
+        }
+    };
+
+    tree.into_iter()
+        .map(|(name, content)| {
+            let name = match &top_dir {
+                Some(top) => name
+                    .strip_prefix(top.as_str())
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .map(str::to_string)
+                    .unwrap_or(name),
+                None => name,
+            };
+            (name, content)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_source_dir() {
+        let tree = vec![
+            ("say-1.4/src/say.lua".to_string(), Vec::new()),
+            ("say-1.4/README.md".to_string(), Vec::new()),
+        ];
+        let stripped = strip_source_dir(tree.clone(), None);
+        assert!(stripped.contains_key("src/say.lua"));
+        let stripped = strip_source_dir(tree, Some("say-1.4/src"));
+        assert!(stripped.contains_key("say.lua"));
+        assert!(stripped.contains_key("say-1.4/README.md"));
+    }
+}
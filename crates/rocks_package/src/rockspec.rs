@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use crate::lua_table::{parse_assignments, LuaValue};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Rockspec {
+    pub package: String,
+    pub version: String,
+    pub source_url: Option<String>,
+    pub source_dir: Option<String>,
+    /// As written in the rockspec, such as `lpeg >= 1.0`.
+    pub dependencies: Vec<String>,
+    /// Pure Lua modules, module name to source file.
+    pub lua_modules: BTreeMap<String, String>,
+    /// Modules that need a C compiler, luap leaves them out.
+    pub native_modules: Vec<String>,
+}
+
+pub fn parse_rockspec(source: &str) -> Result<Rockspec, String> {
+    let globals = parse_assignments(source)?;
+    let string = |value: Option<&LuaValue>| value.and_then(LuaValue::as_str).map(str::to_string);
+
+    let mut rockspec = Rockspec {
+        package: string(globals.get("package")).ok_or("rockspec has no package")?,
+        version: string(globals.get("version")).ok_or("rockspec has no version")?,
+        source_url: string(globals.get("source").and_then(|s| s.get("url"))),
+        source_dir: string(globals.get("source").and_then(|s| s.get("dir"))),
+        ..Default::default()
+    };
+    if let Some(dependencies) = globals.get("dependencies").and_then(LuaValue::as_table) {
+        rockspec.dependencies = dependencies
+            .array
+            .iter()
+            .filter_map(|dep| dep.as_str().map(str::to_string))
+            .collect();
+    }
+
+    let Some(build) = globals.get("build") else {
+        return Ok(rockspec);
+    };
+    let build_type = build
+        .get("type")
+        .and_then(LuaValue::as_str)
+        .unwrap_or("builtin");
+    if let Some(modules) = build.get("modules").and_then(LuaValue::as_table) {
+        if matches!(build_type, "builtin" | "module") {
+            for (module, source) in &modules.fields {
+                match source.as_str() {
+                    Some(file) if file.ends_with(".lua") => {
+                        rockspec
+                            .lua_modules
+                            .insert(module.clone(), file.to_string());
+                    }
+                    _ => rockspec.native_modules.push(module.clone()),
+                }
+            }
+        }
+    }
+    if let Some(lua) = build
+        .get("install")
+        .and_then(|install| install.get("lua"))
+        .and_then(LuaValue::as_table)
+    {
+        for (module, file) in &lua.fields {
+            if let Some(file) = file.as_str() {
+                rockspec
+                    .lua_modules
+                    .insert(module.clone(), file.to_string());
+            }
+        }
+    }
+
+    if !matches!(build_type, "builtin" | "module" | "none") && rockspec.lua_modules.is_empty() {
+        return Err(format!(
+            "{} is built with `{}`, luap only installs pure Lua modules",
+            rockspec.package, build_type
+        ));
+    }
+    Ok(rockspec)
+}
+
+/// Where a module is installed, relative to the root of the rock:
+/// `socket.http` goes to `socket/http.lua`, and an `init.lua` stays one.
+pub fn module_path(module: &str, file: &str) -> String {
+    let path = module.replace('.', "/");
+    if file.ends_with("/init.lua") || file == "init.lua" {
+        format!("{}/init.lua", path)
+    } else {
+        format!("{}.lua", path)
+    }
+}
+
+/// Splits a rockspec dependency into the rock name and a semver requirement,
+/// `lpeg >= 1.0, < 2` becomes `lpeg` and `>=1.0, <2`. The pessimistic `~>`
+/// becomes a tilde requirement, `~=` cannot be written in semver and is left
+/// out.
+pub fn parse_dependency(dependency: &str) -> Option<(String, Option<String>)> {
+    let dependency = dependency.trim();
+    let end = dependency
+        .find(|c: char| c.is_whitespace() || "<>=~".contains(c))
+        .unwrap_or(dependency.len());
+    let (name, constraints) = dependency.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut reqs = Vec::new();
+    for constraint in constraints.split(',') {
+        let constraint = constraint.trim();
+        if constraint.is_empty() {
+            continue;
+        }
+        let (op, version) = ["==", "~=", ">=", "<=", "~>", ">", "<"]
+            .into_iter()
+            .find_map(|op| constraint.strip_prefix(op).map(|version| (op, version)))
+            .unwrap_or(("==", constraint));
+        let version = version.trim();
+        let version = version
+            .rsplit_once('-')
+            .map_or(version, |(version, _)| version);
+        match op {
+            "~=" => {}
+            "==" => reqs.push(format!("={}", version)),
+            "~>" => reqs.push(format!("~{}", version)),
+            op => reqs.push(format!("{}{}", op, version)),
+        }
+    }
+    let req = (!reqs.is_empty()).then(|| reqs.join(", "));
+    Some((name.to_lowercase(), req))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rockspec() {
+        let rockspec = parse_rockspec(
+            r#"
+package = "lpeg"
+version = "1.1.0-1"
+source = { url = "https://example.com/lpeg-1.1.0.tar.gz" }
+dependencies = { "lua >= 5.1" }
+build = {
+   type = "builtin",
+   modules = {
+      lpeg = { "lpcap.c", "lpcode.c" },
+      re = "re.lua",
+      ["lpeg.util"] = "src/util/init.lua",
+   },
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(rockspec.package, "lpeg");
+        assert_eq!(rockspec.dependencies, vec!["lua >= 5.1".to_string()]);
+        assert_eq!(rockspec.native_modules, vec!["lpeg".to_string()]);
+        assert_eq!(rockspec.lua_modules["re"], "re.lua");
+        assert_eq!(
+            module_path("lpeg.util", &rockspec.lua_modules["lpeg.util"]),
+            "lpeg/util/init.lua"
+        );
+        assert_eq!(
+            module_path("socket.http", "src/http.lua"),
+            "socket/http.lua"
+        );
+
+        let err = parse_rockspec(
+            "package = \"luv\"\nversion = \"1.0-1\"\nbuild = { type = \"cmake\" }\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "luv is built with `cmake`, luap only installs pure Lua modules"
+        );
+    }
+
+    #[test]
+    fn test_parse_dependency() {
+        assert_eq!(
+            parse_dependency("lpeg >= 1.0, < 2"),
+            Some(("lpeg".to_string(), Some(">=1.0, <2".to_string())))
+        );
+        assert_eq!(
+            parse_dependency("luasocket ~> 3.0-1"),
+            Some(("luasocket".to_string(), Some("~3.0".to_string())))
+        );
+        assert_eq!(
+            parse_dependency("say == 1.4.1"),
+            Some(("say".to_string(), Some("=1.4.1".to_string())))
+        );
+        assert_eq!(
+            parse_dependency("penlight"),
+            Some(("penlight".to_string(), None))
+        );
+        assert_eq!(
+            parse_dependency("lua>=5.1"),
+            Some(("lua".to_string(), Some(">=5.1".to_string())))
+        );
+    }
+}